@@ -0,0 +1,408 @@
+use crate::{
+    find_zone, load_api_token, ApiErrors, Config, HookError, PropagationConfig, RecordConfig,
+    RetryConfig,
+};
+use reqwest::{
+    header::{self, HeaderValue},
+    Client, RequestBuilder, Response, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use tokio::{
+    sync::{Mutex, OnceCell},
+    time::sleep,
+};
+
+//Structure fields as determined by https://techdocs.akamai.com/linode-api/reference/get-domain-records
+//List endpoints are paginated, page/pages/results describe where this page sits in the full listing
+#[derive(Deserialize)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub page: u32,
+    pub pages: u32,
+    pub results: u32,
+}
+
+pub type Domains = Page<Domain>;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Domain {
+    pub id: i32,
+    pub domain: String,
+}
+
+pub type Records = Page<Record>;
+
+#[derive(Deserialize)]
+pub struct Record {
+    pub id: i32,
+    pub r#type: String,
+    pub name: String,
+    pub target: String,
+    //UTC, e.g. 2018-01-01T00:01:01
+    pub created: Option<String>,
+    pub updated: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TextRecordResult {
+    pub id: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AddedRecord {
+    pub name: String,
+    pub zone: String,
+    pub domain_id: i32,
+    pub record_id: i32,
+    pub token: String,
+    //an identical record was already in the zone, so nothing new was created
+    #[serde(default)]
+    pub reused: bool,
+}
+
+#[derive(Serialize)]
+pub struct TextRecordInsert {
+    pub r#type: String,
+    pub name: String,
+    pub target: String,
+    pub ttl_sec: u32,
+    //left out so Linode applies its own default, it only has an effect on MX and SRV records
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+}
+
+impl TextRecordInsert {
+    fn new(r#type: &str, name: &str, target: &str, settings: &RecordConfig) -> Self {
+        TextRecordInsert {
+            r#type: r#type.to_owned(),
+            name: name.to_owned(),
+            target: target.to_owned(),
+            ttl_sec: settings.ttl_sec,
+            priority: settings.priority,
+        }
+    }
+}
+
+pub fn new_connection(api_token: &str) -> Result<Client, HookError> {
+    let mut headers = header::HeaderMap::new();
+
+    let mut auth_value = HeaderValue::from_str(&format!("Bearer {api_token}")).map_err(|_| {
+        HookError::Config("Linode API token contains invalid characters".to_owned())
+    })?;
+    auth_value.set_sensitive(true);
+    headers.insert(header::AUTHORIZATION, auth_value);
+
+    Ok(reqwest::Client::builder()
+        .default_headers(headers)
+        .build()?)
+}
+
+//Authenticated Linode API client, every request goes through send so the retry policy applies everywhere
+#[derive(Clone)]
+pub struct LinodeDnsClient {
+    pub(crate) client: Client,
+    pub(crate) base_url: String,
+    retry: RetryConfig,
+    pub(crate) propagation: PropagationConfig,
+    pub(crate) journal: Option<PathBuf>,
+    record: RecordConfig,
+    pub(crate) clean_concurrency: usize,
+    //log POST/DELETE calls instead of making them, lookups still go to the API
+    pub(crate) dry_run: bool,
+    //shared by every clone, so concurrent tasks list the domains at most once
+    pub(crate) domains: Arc<OnceCell<Vec<Domain>>>,
+    pub(crate) zone_cache: Option<(PathBuf, u64)>,
+    //zones found by filtered lookups so far, None for names that turned out not to be a zone.
+    //Held for the whole lookup so concurrent tasks wait on one request rather than each sending it
+    pub(crate) zones: Arc<Mutex<HashMap<String, Option<Domain>>>>,
+}
+
+//Largest page size the API allows, keeps the number of requests down for big accounts
+const PAGE_SIZE: u32 = 500;
+
+impl LinodeDnsClient {
+    pub fn new(config: &Config) -> Result<Self, HookError> {
+        Ok(LinodeDnsClient {
+            client: new_connection(&load_api_token(config)?)?,
+            base_url: config.api_url.trim_end_matches('/').to_owned(),
+            retry: config.retry,
+            propagation: config.propagation.to_owned(),
+            journal: config.journal_path(),
+            record: config.record.to_owned(),
+            clean_concurrency: config.clean_concurrency,
+            dry_run: config.dry_run,
+            domains: Arc::new(OnceCell::new()),
+            zones: Arc::new(Mutex::new(HashMap::new())),
+            zone_cache: config
+                .zone_cache_file
+                .to_owned()
+                .map(|path| (path, config.zone_cache_ttl_secs)),
+        })
+    }
+
+    pub async fn send(
+        &self,
+        request: RequestBuilder,
+        description: &str,
+    ) -> Result<Response, HookError> {
+        //a POST that failed on the server may still have created the record, so it's only repeated
+        //when it can't have reached Linode: rate limited, or the connection never opened
+        let idempotent = request
+            .try_clone()
+            .and_then(|request| request.build().ok())
+            .is_some_and(|request| request.method().is_idempotent());
+
+        let mut attempt = 1;
+        loop {
+            let outcome = request
+                .try_clone()
+                .expect("API requests never have streaming bodies")
+                .send()
+                .await;
+
+            //work out whether this attempt is worth repeating, and if the server said how long to wait
+            let retry = match &outcome {
+                Ok(resp)
+                    if resp.status() == StatusCode::TOO_MANY_REQUESTS
+                        || (idempotent && resp.status().is_server_error()) =>
+                {
+                    Some((resp.status().to_string(), retry_after(resp)))
+                }
+                Err(e) if e.is_connect() || (idempotent && e.is_timeout()) => {
+                    Some((e.to_string(), None))
+                }
+                _ => None,
+            };
+
+            match retry {
+                Some((reason, delay)) if attempt < self.retry.max_attempts => {
+                    let delay = delay
+                        .map(|delay| delay.min(Duration::from_millis(self.retry.max_delay_ms)))
+                        .unwrap_or_else(|| self.retry.backoff(attempt));
+                    eprintln!(
+                        "{description} failed with {reason} (attempt {attempt}/{}), retrying in {:.1}s",
+                        self.retry.max_attempts,
+                        delay.as_secs_f64()
+                    );
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => return check_response(description, outcome?).await,
+            }
+        }
+    }
+
+    pub async fn get_all_pages<T: DeserializeOwned>(&self, url: &str) -> Result<Vec<T>, HookError> {
+        self.fetch_pages(url, None).await
+    }
+
+    //only the entries matching Linode's X-Filter syntax, see https://techdocs.akamai.com/linode-api/reference/filtering-and-sorting
+    //A server that rejects the filter (400, or e.g. 431 when the header is too large) gets asked for
+    //the full listing instead, so callers must still check what comes back
+    pub async fn get_filtered_pages<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        filter: &Value,
+    ) -> Result<Vec<T>, HookError> {
+        match self.fetch_pages(url, Some(filter)).await {
+            Err(e) if e.is_client_error() => self.fetch_pages(url, None).await,
+            result => result,
+        }
+    }
+
+    async fn fetch_pages<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        filter: Option<&Value>,
+    ) -> Result<Vec<T>, HookError> {
+        let mut entries = Vec::new();
+        let mut page_number = 1;
+        loop {
+            let mut request = self
+                .client
+                .get(url)
+                .query(&[("page", page_number), ("page_size", PAGE_SIZE)]);
+            let mut description = format!("GET {url}");
+            if let Some(filter) = filter {
+                request = request.header("X-Filter", filter.to_string());
+                description = format!("{description} (X-Filter {filter})");
+            }
+            let page: Page<T> = self.send(request, &description).await?.json().await?;
+            entries.extend(page.data);
+
+            if page.page >= page.pages {
+                return Ok(entries);
+            }
+            page_number = page.page + 1;
+        }
+    }
+
+    pub async fn get_domains(&self) -> Result<Vec<Domain>, HookError> {
+        self.get_all_pages(&format!("{}/domains", self.base_url))
+            .await
+    }
+
+    pub async fn get_filtered_domains(&self, filter: &Value) -> Result<Vec<Domain>, HookError> {
+        self.get_filtered_pages(&format!("{}/domains", self.base_url), filter)
+            .await
+    }
+
+    pub async fn get_records(&self, domain_id: i32) -> Result<Vec<Record>, HookError> {
+        self.get_all_pages(&format!("{}/domains/{domain_id}/records", self.base_url))
+            .await
+    }
+
+    //the zone's TXT records, which is all the challenge lookups need
+    pub async fn get_txt_records(&self, domain_id: i32) -> Result<Vec<Record>, HookError> {
+        self.get_filtered_pages(
+            &format!("{}/domains/{domain_id}/records", self.base_url),
+            &json!({ "type": "TXT" }),
+        )
+        .await
+    }
+
+    pub async fn get_domain_info(
+        &self,
+        domain_name: &str,
+    ) -> Result<(String, String, i32), HookError> {
+        self.prefetch_zones(&[domain_name]).await?;
+        let found = match self.uses_domain_list() {
+            true => find_zone(self.cached_domains().await?, domain_name)
+                .map(|(subdomain, entry)| (subdomain, entry.to_owned())),
+            false => find_zone(&self.looked_up_zones(domain_name).await, domain_name)
+                .map(|(subdomain, entry)| (subdomain, entry.to_owned())),
+        };
+
+        match found {
+            Some((subdomain, entry)) => Ok((subdomain, entry.domain, entry.id)),
+            None => Err(HookError::ZoneNotFound(domain_name.to_owned())),
+        }
+    }
+
+    pub async fn get_record_id(
+        &self,
+        domain_id: i32,
+        subdomain: &str,
+        token: &str,
+    ) -> Result<Option<i32>, HookError> {
+        let ids = self.get_record_ids(domain_id, subdomain, token).await?;
+        Ok(ids.first().copied())
+    }
+
+    //every matching challenge record, earlier runs may have left duplicates behind
+    pub async fn get_record_ids(
+        &self,
+        domain_id: i32,
+        subdomain: &str,
+        token: &str,
+    ) -> Result<Vec<i32>, HookError> {
+        let record_name = challenge_record_name(subdomain);
+        self.find_txt_records(domain_id, &record_name, token).await
+    }
+
+    //ids of the TXT records with exactly this name (relative to the zone) and value
+    pub async fn find_txt_records(
+        &self,
+        domain_id: i32,
+        record_name: &str,
+        token: &str,
+    ) -> Result<Vec<i32>, HookError> {
+        let records: Vec<Record> = self
+            .get_filtered_pages(
+                &format!("{}/domains/{domain_id}/records", self.base_url),
+                &json!({ "type": "TXT", "name": record_name }),
+            )
+            .await?;
+        Ok(matching_txt_records(&records, record_name, token))
+    }
+
+    pub async fn add_txt_record(
+        &self,
+        domain_name: &str,
+        token: &str,
+    ) -> Result<AddedRecord, HookError> {
+        let (subdomain, base_domain, domain_id) = self.get_domain_info(domain_name).await?;
+        let added = |record_id, reused| AddedRecord {
+            name: domain_name.to_owned(),
+            zone: base_domain.to_owned(),
+            domain_id,
+            record_id,
+            token: token.to_owned(),
+            reused,
+        };
+
+        //a rerun of deploy_challenge with the same token adopts the record it made last time
+        let existing = self.find_txt_records(domain_id, &subdomain, token).await?;
+        if let Some(&record_id) = existing.first() {
+            return Ok(added(record_id, true));
+        }
+
+        let record = TextRecordInsert::new("TXT", &subdomain, token, &self.record);
+
+        let url = format!("{}/domains/{domain_id}/records", self.base_url);
+        if self.dry_run {
+            println!(
+                "[dry run] POST {url} ({domain_name} in zone {base_domain} - id:{domain_id}) {}",
+                serde_json::to_string(&record).expect("record always serializes")
+            );
+            return Ok(added(0, false));
+        }
+
+        let request = self.client.post(&url).json(&record);
+        let entry: TextRecordResult = self
+            .send(request, &format!("POST {url} ({domain_name})"))
+            .await?
+            .json()
+            .await?;
+        Ok(added(entry.id, false))
+    }
+
+    pub async fn remove_txt_record(&self, domain_id: i32, record_id: i32) -> Result<(), HookError> {
+        let url = format!("{}/domains/{domain_id}/records/{record_id}", self.base_url);
+        if self.dry_run {
+            println!("[dry run] DELETE {url}");
+            return Ok(());
+        }
+
+        let request = self.client.delete(&url);
+        self.send(request, &format!("DELETE {url}")).await?;
+        Ok(())
+    }
+}
+
+//_acme-challenge record name, relative to the zone, for a subdomain as returned by find_zone
+pub(crate) fn challenge_record_name(subdomain: &str) -> String {
+    match subdomain {
+        "" => "_acme-challenge".to_owned(),
+        hostname => format!("_acme-challenge.{hostname}"),
+    }
+}
+
+pub(crate) fn matching_txt_records(records: &[Record], record_name: &str, token: &str) -> Vec<i32> {
+    records
+        .iter()
+        .filter(|record| {
+            record.r#type == "TXT" && record.name == record_name && record.target == token
+        })
+        .map(|record| record.id)
+        .collect()
+}
+
+//Retry-After in seconds, HTTP-date values fall back to the normal backoff
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let seconds = resp.headers().get(header::RETRY_AFTER)?.to_str().ok()?;
+    seconds.trim().parse().ok().map(Duration::from_secs)
+}
+
+//Passes successful responses through, otherwise reads Linode's error payload into a HookError
+pub async fn check_response(request: &str, resp: Response) -> Result<Response, HookError> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    //error bodies aren't guaranteed to be JSON (e.g. from a proxy), the status still says enough
+    let errors = resp.json::<ApiErrors>().await.unwrap_or_default().errors;
+    Err(HookError::from_response(request.to_owned(), status, errors))
+}
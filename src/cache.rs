@@ -0,0 +1,149 @@
+use crate::{journal::temporary_path, Domain, HookError, LinodeDnsClient};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("clock is after 1970")
+        .as_secs()
+}
+
+//suffixes of name that could be the zone holding it, longest first. Labels like _acme-challenge
+//can't start a zone name, so suffixes beginning with one are skipped
+fn zone_candidates(name: &str) -> Vec<String> {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let labels: Vec<&str> = name.split('.').collect();
+    (0..labels.len())
+        .filter(|&i| !labels[i].starts_with('_'))
+        .map(|i| labels[i..].join("."))
+        .collect()
+}
+
+//candidates per filtered request, so the X-Filter header stays well within header size limits
+const ZONE_LOOKUP_BATCH: usize = 50;
+
+//The account's domain list as saved to zone_cache_file, fetched_at in seconds since 1970
+#[derive(Serialize, Deserialize)]
+pub struct ZoneCache {
+    pub fetched_at: u64,
+    pub domains: Vec<Domain>,
+}
+
+impl ZoneCache {
+    //None when there is no cache yet, it can't be read or it is older than ttl_secs
+    pub fn load(path: &Path, ttl_secs: u64) -> Option<Self> {
+        let cache: ZoneCache = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+        (unix_time().saturating_sub(cache.fetched_at) < ttl_secs).then_some(cache)
+    }
+
+    //written to a temporary file first, the same as the record journal
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let temporary = temporary_path(path);
+        let contents = serde_json::to_string(self).expect("zone cache always serializes");
+        fs::write(&temporary, contents)?;
+        fs::rename(&temporary, path)
+    }
+}
+
+impl LinodeDnsClient {
+    //the domain list, fetched at most once per client and reused from zone_cache_file while it is fresh
+    pub async fn cached_domains(&self) -> Result<&[Domain], HookError> {
+        let domains = self
+            .domains
+            .get_or_try_init(|| async {
+                let Some((path, ttl_secs)) = &self.zone_cache else {
+                    return self.get_domains().await;
+                };
+                if let Some(cache) = ZoneCache::load(path, *ttl_secs) {
+                    return Ok(cache.domains);
+                }
+
+                let cache = ZoneCache {
+                    fetched_at: unix_time(),
+                    domains: self.get_domains().await?,
+                };
+                //a cache that can't be written only costs another listing next time
+                if let Err(e) = cache.save(path) {
+                    eprintln!("Failed to write zone cache {}: {e}", path.display());
+                }
+                Ok(cache.domains)
+            })
+            .await?;
+        Ok(domains)
+    }
+
+    //the full domain list is used once it has been fetched anyway, or when it is cached on disk
+    pub(crate) fn uses_domain_list(&self) -> bool {
+        self.zone_cache.is_some() || self.domains.initialized()
+    }
+
+    //resolves the zones of every name up front, so tasks working on them concurrently don't each
+    //make their own request
+    pub async fn prefetch_zones(&self, names: &[&str]) -> Result<(), HookError> {
+        match self.uses_domain_list() {
+            true => self.cached_domains().await.map(|_| ()),
+            false => self.lookup_zones(names).await,
+        }
+    }
+
+    //finds the zones that could hold any of names with filtered requests of up to ZONE_LOOKUP_BATCH
+    //candidates each, so a large account isn't listed in full. Already known candidates aren't
+    //asked for again
+    pub async fn lookup_zones(&self, names: &[&str]) -> Result<(), HookError> {
+        let mut zones = self.zones.lock().await;
+        let mut missing: Vec<String> = names
+            .iter()
+            .flat_map(|name| zone_candidates(name))
+            .filter(|candidate| !zones.contains_key(candidate))
+            .collect();
+        missing.sort();
+        missing.dedup();
+
+        for batch in missing.chunks(ZONE_LOOKUP_BATCH) {
+            let filter = json!({
+                "+or": batch.iter().map(|candidate| json!({ "domain": candidate })).collect::<Value>()
+            });
+            let found = self.get_filtered_domains(&filter).await?;
+
+            for candidate in batch {
+                let zone = found
+                    .iter()
+                    .find(|domain| domain.domain.eq_ignore_ascii_case(candidate));
+                zones.insert(candidate.to_owned(), zone.cloned());
+            }
+        }
+        Ok(())
+    }
+
+    //the zones lookup_zones found that could hold name
+    pub(crate) async fn looked_up_zones(&self, name: &str) -> Vec<Domain> {
+        let zones = self.zones.lock().await;
+        zone_candidates(name)
+            .iter()
+            .filter_map(|candidate| zones.get(candidate).cloned().flatten())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candidates_run_from_the_full_name_to_the_tld() {
+        assert_eq!(
+            zone_candidates("www.Example.com."),
+            ["www.example.com", "example.com", "com"]
+        );
+        assert_eq!(
+            zone_candidates("_acme-challenge.www.example.com"),
+            ["www.example.com", "example.com", "com"]
+        );
+    }
+}
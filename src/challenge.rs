@@ -0,0 +1,286 @@
+use crate::{
+    api::{challenge_record_name, matching_txt_records},
+    authoritative_nameservers, wait_for_record_population, AddedRecord, Challenge, HookError,
+    LinodeDnsClient, Record,
+};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    net::SocketAddr,
+    sync::Arc,
+};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+impl LinodeDnsClient {
+    pub async fn deploy_challenge(&self, challenges: &[Challenge]) -> Result<(), HookError> {
+        println!(
+            "**********************************************************************************"
+        );
+        println!("Deploying TXT records for listed challenges:");
+
+        //anything created before a failure is deleted again rather than left in the zone
+        let mut created = Vec::new();
+        let result = self.deploy_records(challenges, &mut created).await;
+        if result.is_err() && !created.is_empty() {
+            self.roll_back(&created).await;
+        }
+        result
+    }
+
+    async fn deploy_records(
+        &self,
+        challenges: &[Challenge],
+        created: &mut Vec<AddedRecord>,
+    ) -> Result<(), HookError> {
+        let domains: Vec<&str> = challenges.iter().map(|c| c.domain.as_str()).collect();
+        self.prefetch_zones(&domains).await?;
+
+        let mut deploy_set = JoinSet::new();
+        let mut confirm_set = JoinSet::new();
+        let mut zone_nameservers: HashMap<String, Vec<SocketAddr>> = HashMap::new();
+
+        for challenge in challenges {
+            let client = self.clone();
+            let target = format!("_acme-challenge.{}", challenge.domain);
+            let token = challenge.token_value.to_owned();
+
+            //deploy text records asynchronously
+            deploy_set.spawn(async move {
+                let outcome = client.add_txt_record(&target, &token).await;
+                (target, outcome)
+            });
+        }
+
+        //wait for every task, even after one fails, so no record is created behind our back
+        let mut added = Vec::new();
+        let mut first_error = None;
+        while let Some(result) = deploy_set.join_next().await {
+            let record = match result {
+                Ok((_, Ok(record))) => record,
+                Ok((target, Err(e))) => {
+                    println!("FAILED to add token for '{target}' ({e})");
+                    first_error.get_or_insert(e);
+                    continue;
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e.into());
+                    continue;
+                }
+            };
+            if self.dry_run && !record.reused {
+                continue;
+            }
+
+            println!(
+                "{} token '{}' for '{}' - id:{}",
+                if record.reused { "Reused" } else { "Added" },
+                record.token,
+                record.name,
+                record.record_id
+            );
+            if !record.reused {
+                created.push(record.clone());
+            }
+            //a reused record may already be journaled by the run that created it
+            let journaled = self.update_journal(|journal| {
+                journal.forget(record.domain_id, record.record_id);
+                journal.records.push(record.clone())
+            });
+            if let Err(e) = journaled {
+                first_error.get_or_insert(e);
+            }
+            added.push(record);
+        }
+        if let Some(e) = first_error {
+            return Err(e);
+        }
+
+        if self.dry_run {
+            println!("Dry run, nothing was added so there is no propagation to wait for");
+            println!(
+                "**********************************************************************************"
+            );
+            return Ok(());
+        }
+
+        for record in added {
+            //check every authoritative nameserver of the zone, discovered once per zone,
+            //unless the config names the nameservers to check
+            let nameservers = match zone_nameservers.get(&record.zone) {
+                _ if !self.propagation.nameservers.is_empty() => {
+                    self.propagation.nameservers.to_owned()
+                }
+                Some(nameservers) => nameservers.to_owned(),
+                None => {
+                    let nameservers = authoritative_nameservers(&record.zone).await?;
+                    zone_nameservers.insert(record.zone.to_owned(), nameservers.to_owned());
+                    nameservers
+                }
+            };
+
+            //run dns lookup requests asynchronously, keeping hold of which record each one is for
+            let propagation = self.propagation.to_owned();
+            confirm_set.spawn(async move {
+                let outcome = wait_for_record_population(
+                    propagation,
+                    nameservers,
+                    record.name.to_owned(),
+                    record.token.to_owned(),
+                )
+                .await;
+                (record, outcome)
+            });
+        }
+
+        println!("All records deployed. Please WAIT for Linode DNS to refresh");
+        println!(
+            "This normally takes 2 minutes or so (giving up after {} seconds)",
+            self.propagation.timeout_secs
+        );
+        println!("...");
+
+        let mut outcomes = Vec::new();
+        while let Some(result) = confirm_set.join_next().await {
+            outcomes.push(result?);
+        }
+        outcomes.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+
+        println!("Propagation summary:");
+        let mut failed = Vec::new();
+        for (record, outcome) in outcomes {
+            match outcome {
+                Ok(_) => println!("  {} - token '{}' confirmed", record.name, record.token),
+                Err(e) => {
+                    failed.push(record.name.to_owned());
+                    println!(
+                        "  {} - token '{}' NOT PROPAGATED ({e})",
+                        record.name, record.token
+                    );
+                }
+            }
+        }
+
+        if !failed.is_empty() {
+            println!(
+                "**********************************************************************************"
+            );
+            failed.dedup();
+            return Err(HookError::DnsTimeout {
+                names: failed,
+                timeout_secs: self.propagation.timeout_secs,
+            });
+        }
+
+        println!("All records confirmed as available");
+        println!(
+            "**********************************************************************************"
+        );
+        Ok(())
+    }
+
+    //records that can't be deleted stay in the journal for clean_challenge, request_failure or gc
+    async fn roll_back(&self, created: &[AddedRecord]) {
+        println!(
+            "Rolling back the {} record(s) added by this run:",
+            created.len()
+        );
+        for record in created {
+            let removed = self
+                .remove_and_report(
+                    record.domain_id,
+                    record.record_id,
+                    &record.name,
+                    &record.token,
+                )
+                .await;
+            if removed.is_err() {
+                continue;
+            }
+            let forgotten =
+                self.update_journal(|journal| journal.forget(record.domain_id, record.record_id));
+            if let Err(e) = forgotten {
+                println!("{e}");
+            }
+        }
+    }
+
+    pub async fn clean_challenge(&self, challenges: &[Challenge]) -> Result<(), HookError> {
+        let journal = self.load_journal()?;
+        let mut zone_records: HashMap<i32, Vec<Record>> = HashMap::new();
+
+        //zones are only looked up for challenges that aren't in the journal, all in one go
+        let unjournaled: Vec<&str> = challenges
+            .iter()
+            .filter(|c| {
+                let name = format!("_acme-challenge.{}", c.domain);
+                journal.find(&name, &c.token_value).is_none()
+            })
+            .map(|c| c.domain.as_str())
+            .collect();
+        if !unjournaled.is_empty() {
+            self.prefetch_zones(&unjournaled).await?;
+        }
+
+        let limit = Arc::new(Semaphore::new(self.clean_concurrency.max(1)));
+        let mut remove_set = JoinSet::new();
+
+        for challenge in challenges {
+            let name = format!("_acme-challenge.{}", challenge.domain);
+
+            //records deploy_challenge noted can be deleted straight away, otherwise scan the zone for them
+            let (domain_id, ids) = match journal.find(&name, &challenge.token_value) {
+                Some(record) => (record.domain_id, vec![record.record_id]),
+                None => {
+                    let (subdomain, _base_domain, domain_id) =
+                        self.get_domain_info(&challenge.domain).await?;
+                    let records = match zone_records.entry(domain_id) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            entry.insert(self.get_txt_records(domain_id).await?)
+                        }
+                    };
+                    let ids = matching_txt_records(
+                        records,
+                        &challenge_record_name(&subdomain),
+                        &challenge.token_value,
+                    );
+                    (domain_id, ids)
+                }
+            };
+
+            //delete duplicates as well, up to clean_concurrency at once
+            for id in ids {
+                let client = self.clone();
+                let limit = limit.clone();
+                let domain = challenge.domain.to_owned();
+                let token = challenge.token_value.to_owned();
+                remove_set.spawn(async move {
+                    let _permit = limit
+                        .acquire_owned()
+                        .await
+                        .expect("semaphore is never closed");
+                    let outcome = client
+                        .remove_and_report(domain_id, id, &domain, &token)
+                        .await;
+                    (domain_id, id, outcome)
+                });
+            }
+        }
+
+        let mut first_error = None;
+        while let Some(result) = remove_set.join_next().await {
+            match result? {
+                (domain_id, id, Ok(_)) => {
+                    self.update_journal(|journal| journal.forget(domain_id, id))?
+                }
+                (_, _, Err(e)) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,477 @@
+use crate::HookError;
+use serde::Deserialize;
+use std::{
+    env, fs,
+    hash::{BuildHasher, RandomState},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+//Optional JSON settings file, see README for the available keys
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub token: Option<String>,
+    pub token_file: Option<PathBuf>,
+    pub api_url: String,
+    pub propagation: PropagationConfig,
+    pub retry: RetryConfig,
+    pub record: RecordConfig,
+    //where created records are noted until clean_challenge removes them, see journal_path
+    pub journal_file: Option<PathBuf>,
+    //delete every journaled record when dehydrated reports a failed ACME request
+    pub cleanup_on_request_failure: bool,
+    //how many records clean_challenge deletes at once
+    pub clean_concurrency: usize,
+    //the domain list is kept here between hook calls for zone_cache_ttl_secs, only when set
+    pub zone_cache_file: Option<PathBuf>,
+    pub zone_cache_ttl_secs: u64,
+    //challenge records untouched for longer than this are removed by gc
+    pub gc_max_age_hours: u64,
+    //only log the records that would be created or deleted, and the deploy steps that would run
+    pub dry_run: bool,
+    //run in order by deploy_cert on every new certificate
+    pub deploy: Vec<DeployAction>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            token: None,
+            token_file: None,
+            api_url: "https://api.linode.com/v4".to_owned(),
+            propagation: PropagationConfig::default(),
+            retry: RetryConfig::default(),
+            record: RecordConfig::default(),
+            journal_file: None,
+            cleanup_on_request_failure: false,
+            clean_concurrency: 10,
+            zone_cache_file: None,
+            zone_cache_ttl_secs: 3600,
+            gc_max_age_hours: 24,
+            dry_run: false,
+            deploy: Vec::new(),
+        }
+    }
+}
+
+//How long to wait for new records to show up on the nameservers, defaults to polling every 15 seconds for 20 minutes
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PropagationConfig {
+    pub timeout_secs: u64,
+    pub initial_delay_secs: u64,
+    pub poll_interval_secs: u64,
+    //double the poll interval after every miss, up to max_poll_interval_secs
+    pub backoff: bool,
+    pub max_poll_interval_secs: u64,
+    //checked instead of the zone's authoritative nameservers when set
+    pub nameservers: Vec<SocketAddr>,
+}
+
+impl Default for PropagationConfig {
+    fn default() -> Self {
+        PropagationConfig {
+            timeout_secs: 1200,
+            initial_delay_secs: 0,
+            poll_interval_secs: 15,
+            backoff: false,
+            max_poll_interval_secs: 120,
+            nameservers: Vec::new(),
+        }
+    }
+}
+
+//Retries for rate limited (429) and server error (5xx) API responses, and for failed connections.
+//Without a Retry-After header the delay doubles each attempt, with jitter, up to max_delay_ms
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 5,
+            base_delay_ms: 1000,
+            max_delay_ms: 30000,
+        }
+    }
+}
+
+impl RetryConfig {
+    //somewhere between half and all of the exponential delay for this attempt, so concurrent tasks spread out
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay_ms
+            .saturating_mul(2u64.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay_ms);
+        let jitter = match exponential / 2 {
+            0 => 0,
+            half => RandomState::new().hash_one(attempt) % (half + 1),
+        };
+        Duration::from_millis(exponential - exponential / 2 + jitter)
+    }
+}
+
+//Extra fields sent with every challenge record created, ttl_sec defaults to the 30 second minimum Linode allows
+//so a retried validation isn't held up by a cached old value
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordConfig {
+    pub ttl_sec: u32,
+    //Linode ignores priority on TXT records (tags are CAA only, so aren't offered)
+    pub priority: Option<u8>,
+}
+
+impl Default for RecordConfig {
+    fn default() -> Self {
+        RecordConfig {
+            ttl_sec: 30,
+            priority: None,
+        }
+    }
+}
+
+//One of the files dehydrated passes to deploy_cert
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CertFile {
+    Key,
+    Cert,
+    Fullchain,
+    Chain,
+}
+
+//A deploy_cert step, written in the config file as {"action": "copy", ...}.
+//owner and group are names or numeric ids, mode is octal like "0640"; when left out the file keeps
+//the hook's user and group, and is only readable by them if it holds the private key
+#[derive(Deserialize, Debug)]
+#[serde(tag = "action", rename_all = "lowercase", deny_unknown_fields)]
+pub enum DeployAction {
+    Copy {
+        file: CertFile,
+        to: PathBuf,
+        owner: Option<String>,
+        group: Option<String>,
+        mode: Option<String>,
+    },
+    //private key followed by the full chain in one file, as HAProxy expects
+    Combine {
+        to: PathBuf,
+        owner: Option<String>,
+        group: Option<String>,
+        mode: Option<String>,
+    },
+    //full chain and key uploaded to an HTTPS NodeBalancer config, checked by reading back its fingerprint
+    NodeBalancer {
+        nodebalancer_id: i32,
+        config_id: i32,
+    },
+    //run with sh -c, failing on a non-zero exit status or when it outlasts timeout_secs
+    Command {
+        command: String,
+        #[serde(default = "default_command_timeout")]
+        timeout_secs: u64,
+    },
+}
+
+fn default_command_timeout() -> u64 {
+    60
+}
+
+//Replaces value with the contents of the environment variable, if set
+fn env_override<T: FromStr>(name: &str, value: &mut T) -> Result<(), HookError> {
+    if let Ok(setting) = env::var(name) {
+        *value = setting
+            .trim()
+            .parse()
+            .map_err(|_| HookError::Config(format!("Invalid value '{setting}' for {name}")))?;
+    }
+    Ok(())
+}
+
+impl Config {
+    //LINODE_DNS_CONFIG takes priority, otherwise look for linode-dns.json in dehydrated's BASEDIR
+    pub fn path() -> Option<PathBuf> {
+        if let Ok(path) = env::var("LINODE_DNS_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+        let path = Path::new(&env::var_os("BASEDIR")?).join("linode-dns.json");
+        path.exists().then_some(path)
+    }
+
+    pub fn load() -> Result<Config, HookError> {
+        let mut config = match Config::path() {
+            Some(path) => Config::read(&path)?,
+            None => Config::default(),
+        };
+        config.apply_env()?;
+
+        //a zero interval would query the nameservers back to back for the whole timeout
+        if config.propagation.poll_interval_secs == 0 {
+            return Err(HookError::Config(
+                "The propagation poll interval must be at least 1 second".to_owned(),
+            ));
+        }
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Config, HookError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| HookError::Config(format!("Failed to read {}: {e}", path.display())))?;
+        let config: Config = serde_json::from_str(&contents)
+            .map_err(|e| HookError::Config(format!("Failed to parse {}: {e}", path.display())))?;
+        //a token kept in the config file needs the same protection as a token file
+        if config.token.is_some() {
+            check_private(path, "Config file")?;
+        }
+        Ok(config)
+    }
+
+    //journal_file if set, otherwise linode-dns-journal.json in dehydrated's BASEDIR
+    pub fn journal_path(&self) -> Option<PathBuf> {
+        match &self.journal_file {
+            Some(path) => Some(path.to_owned()),
+            None => Some(Path::new(&env::var_os("BASEDIR")?).join("linode-dns-journal.json")),
+        }
+    }
+
+    //environment variables take priority over the config file
+    fn apply_env(&mut self) -> Result<(), HookError> {
+        env_override("LINODE_API_URL", &mut self.api_url)?;
+        if let Some(path) = env::var_os("LINODE_DNS_JOURNAL") {
+            self.journal_file = Some(PathBuf::from(path));
+        }
+        if let Some(path) = env::var_os("LINODE_DNS_ZONE_CACHE") {
+            self.zone_cache_file = Some(PathBuf::from(path));
+        }
+        env_override("LINODE_DNS_ZONE_CACHE_TTL", &mut self.zone_cache_ttl_secs)?;
+        env_override(
+            "LINODE_DNS_CLEANUP_ON_FAILURE",
+            &mut self.cleanup_on_request_failure,
+        )?;
+        env_override("LINODE_DNS_CLEAN_CONCURRENCY", &mut self.clean_concurrency)?;
+        env_override("LINODE_DNS_GC_MAX_AGE_HOURS", &mut self.gc_max_age_hours)?;
+        env_override("LINODE_DNS_DRY_RUN", &mut self.dry_run)?;
+
+        let propagation = &mut self.propagation;
+        env_override(
+            "LINODE_DNS_PROPAGATION_TIMEOUT",
+            &mut propagation.timeout_secs,
+        )?;
+        env_override(
+            "LINODE_DNS_PROPAGATION_INITIAL_DELAY",
+            &mut propagation.initial_delay_secs,
+        )?;
+        env_override(
+            "LINODE_DNS_PROPAGATION_POLL_INTERVAL",
+            &mut propagation.poll_interval_secs,
+        )?;
+        env_override("LINODE_DNS_PROPAGATION_BACKOFF", &mut propagation.backoff)?;
+        env_override(
+            "LINODE_DNS_PROPAGATION_MAX_POLL_INTERVAL",
+            &mut propagation.max_poll_interval_secs,
+        )?;
+
+        env_override("LINODE_DNS_RECORD_TTL", &mut self.record.ttl_sec)?;
+
+        let retry = &mut self.retry;
+        env_override("LINODE_DNS_RETRY_MAX_ATTEMPTS", &mut retry.max_attempts)?;
+        env_override("LINODE_DNS_RETRY_BASE_DELAY_MS", &mut retry.base_delay_ms)?;
+        env_override("LINODE_DNS_RETRY_MAX_DELAY_MS", &mut retry.max_delay_ms)?;
+        Ok(())
+    }
+}
+
+//Token sources in priority order: LINODE_TOKEN, LINODE_TOKEN_FILE, then the config file
+pub fn load_api_token(config: &Config) -> Result<String, HookError> {
+    resolve_token(
+        env::var("LINODE_TOKEN").ok(),
+        env::var_os("LINODE_TOKEN_FILE").map(PathBuf::from),
+        config,
+    )
+}
+
+fn resolve_token(
+    env_token: Option<String>,
+    env_token_file: Option<PathBuf>,
+    config: &Config,
+) -> Result<String, HookError> {
+    if let Some(token) = env_token {
+        if !token.trim().is_empty() {
+            return Ok(token.trim().to_owned());
+        }
+    }
+
+    let token_file = env_token_file.or_else(|| config.token_file.clone());
+    if let Some(path) = token_file {
+        return read_token_file(&path);
+    }
+
+    match &config.token {
+        Some(token) if !token.trim().is_empty() => Ok(token.trim().to_owned()),
+        _ => Err(HookError::Config(
+            "No Linode API token found: set LINODE_TOKEN, LINODE_TOKEN_FILE, or 'token'/'token_file' in the config file".to_owned(),
+        )),
+    }
+}
+
+//refuse tokens that other users on the host could read
+fn check_private(path: &Path, kind: &str) -> Result<(), HookError> {
+    let metadata = fs::metadata(path)
+        .map_err(|e| HookError::Config(format!("Failed to read {kind} {}: {e}", path.display())))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Err(HookError::Config(format!(
+                "{kind} {} has permissions {mode:o}, it must not be accessible by group or others (try chmod 600)",
+                path.display()
+            )));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+    Ok(())
+}
+
+fn read_token_file(path: &Path) -> Result<String, HookError> {
+    check_private(path, "Token file")?;
+
+    let token = fs::read_to_string(path).map_err(|e| {
+        HookError::Config(format!("Failed to read token file {}: {e}", path.display()))
+    })?;
+    match token.trim() {
+        "" => Err(HookError::Config(format!(
+            "Token file {} is empty",
+            path.display()
+        ))),
+        token => Ok(token.to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    //a token file only the current user can read, removed again by the caller
+    fn private_file(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("linode-dns-{name}-{}", process::id()));
+        fs::write(&path, contents).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn token_sources_are_tried_in_order() {
+        let path = private_file("ordered-token", "file-token\n");
+        let config = Config {
+            token: Some("config-token".to_owned()),
+            ..Config::default()
+        };
+
+        let resolve = |env_token: Option<&str>, env_token_file: Option<&Path>| {
+            resolve_token(
+                env_token.map(str::to_owned),
+                env_token_file.map(Path::to_owned),
+                &config,
+            )
+            .unwrap()
+        };
+        assert_eq!(resolve(Some("env-token"), Some(&path)), "env-token");
+        assert_eq!(resolve(Some(" "), Some(&path)), "file-token");
+        assert_eq!(resolve(None, None), "config-token");
+        assert!(resolve_token(None, None, &Config::default()).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn empty_token_files_are_rejected() {
+        let path = private_file("empty-token", " \n");
+        let error = read_token_file(&path).unwrap_err();
+        assert!(error.to_string().contains("is empty"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn readable_secrets_are_rejected() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = private_file("shared-token", "file-token");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        let error = read_token_file(&path).unwrap_err();
+        assert!(error.to_string().contains("permissions 640"));
+        fs::remove_file(&path).unwrap();
+
+        //the config file only has to be private when it holds the token itself
+        let path = private_file("shared-config", r#"{"token": "config-token"}"#);
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(Config::read(&path).is_err());
+        fs::write(&path, r#"{"token_file": "/etc/dehydrated/linode-token"}"#).unwrap();
+        assert!(Config::read(&path).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn deploy_actions_are_read_by_name() {
+        let config: Config = serde_json::from_str(
+            r#"{"deploy": [
+                {"action": "copy", "file": "fullchain", "to": "/etc/nginx/cert.pem", "mode": "0644"},
+                {"action": "combine", "to": "/etc/haproxy/cert.pem", "owner": "haproxy"},
+                {"action": "command", "command": "systemctl reload nginx"}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            &config.deploy[0],
+            DeployAction::Copy { file: CertFile::Fullchain, mode: Some(mode), .. } if mode == "0644"
+        ));
+        assert!(matches!(
+            &config.deploy[1],
+            DeployAction::Combine { owner: Some(owner), .. } if owner == "haproxy"
+        ));
+        assert!(matches!(
+            &config.deploy[2],
+            DeployAction::Command {
+                timeout_secs: 60,
+                ..
+            }
+        ));
+
+        let typo =
+            r#"{"deploy": [{"action": "copy", "file": "key", "to": "/tmp/k", "mdoe": "0600"}]}"#;
+        assert!(serde_json::from_str::<Config>(typo).is_err());
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let retry = RetryConfig {
+            max_attempts: 10,
+            base_delay_ms: 1000,
+            max_delay_ms: 5000,
+        };
+        for _ in 0..20 {
+            let first = retry.backoff(1).as_millis();
+            let third = retry.backoff(3).as_millis();
+            let tenth = retry.backoff(10).as_millis();
+            assert!((500..=1000).contains(&first));
+            assert!((2000..=4000).contains(&third));
+            assert!((2500..=5000).contains(&tenth));
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use crate::{
+    journal::temporary_path, CertFile, Config, DeployAction, DeployCert, HookError, LinodeDnsClient,
+};
+#[cfg(unix)]
+use std::{
+    ffi::{CStr, CString},
+    mem::MaybeUninit,
+    ptr,
+};
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::Path,
+    process::Stdio,
+    time::Duration,
+};
+use tokio::{process::Command, time::timeout};
+
+//Runs each step in config.deploy on a new certificate in order, stopping at the first one that fails
+pub async fn run_deploy_actions(config: &Config, cert: &DeployCert) -> Result<(), HookError> {
+    let actions = &config.deploy;
+    //only steps that talk to Linode need an API token, and a dry run doesn't run any step
+    let client = match !config.dry_run
+        && actions
+            .iter()
+            .any(|action| matches!(action, DeployAction::NodeBalancer { .. }))
+    {
+        true => Some(LinodeDnsClient::new(config)?),
+        false => None,
+    };
+
+    for (step, action) in actions.iter().enumerate() {
+        let description = describe(action, cert);
+        let step = format!("Deploy step {}/{}", step + 1, actions.len());
+        if config.dry_run {
+            println!("{step} [dry run] would {description}");
+            continue;
+        }
+
+        match run_action(action, cert, client.as_ref()).await {
+            Ok(()) => println!("{step} done: {description}"),
+            Err(message) => {
+                println!("{step} FAILED: {description} ({message})");
+                return Err(HookError::Deploy(format!("{description}: {message}")));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cert_path(cert: &DeployCert, file: CertFile) -> &Path {
+    match file {
+        CertFile::Key => &cert.keyfile,
+        CertFile::Cert => &cert.certfile,
+        CertFile::Fullchain => &cert.fullchainfile,
+        CertFile::Chain => &cert.chainfile,
+    }
+}
+
+fn describe(action: &DeployAction, cert: &DeployCert) -> String {
+    match action {
+        DeployAction::Copy { file, to, .. } => format!(
+            "copy {} to {}",
+            cert_path(cert, *file).display(),
+            to.display()
+        ),
+        DeployAction::Combine { to, .. } => format!(
+            "combine {} and {} into {}",
+            cert.keyfile.display(),
+            cert.fullchainfile.display(),
+            to.display()
+        ),
+        DeployAction::NodeBalancer {
+            nodebalancer_id,
+            config_id,
+        } => format!(
+            "upload {} to NodeBalancer {nodebalancer_id} config {config_id}",
+            cert.fullchainfile.display()
+        ),
+        DeployAction::Command { command, .. } => format!("run '{command}'"),
+    }
+}
+
+async fn run_action(
+    action: &DeployAction,
+    cert: &DeployCert,
+    client: Option<&LinodeDnsClient>,
+) -> Result<(), String> {
+    let read =
+        |path: &Path| fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()));
+
+    match action {
+        DeployAction::Copy {
+            file,
+            to,
+            owner,
+            group,
+            mode,
+        } => {
+            let contents = read(cert_path(cert, *file))?;
+            let private = *file == CertFile::Key;
+            install(&contents, to, owner, group, mode, private)
+        }
+        DeployAction::Combine {
+            to,
+            owner,
+            group,
+            mode,
+        } => {
+            let mut contents = read(&cert.keyfile)?;
+            if !contents.ends_with(b"\n") {
+                contents.push(b'\n');
+            }
+            contents.extend(read(&cert.fullchainfile)?);
+            install(&contents, to, owner, group, mode, true)
+        }
+        DeployAction::NodeBalancer {
+            nodebalancer_id,
+            config_id,
+        } => {
+            let client = client.expect("client is created when there are NodeBalancer steps");
+            let fullchain = String::from_utf8(read(&cert.fullchainfile)?)
+                .map_err(|_| "the full chain is not a PEM file".to_owned())?;
+            let key = String::from_utf8(read(&cert.keyfile)?)
+                .map_err(|_| "the private key is not a PEM file".to_owned())?;
+            client
+                .upload_nodebalancer_certificate(*nodebalancer_id, *config_id, &fullchain, &key)
+                .await
+                .map_err(|e| e.to_string())
+        }
+        DeployAction::Command {
+            command,
+            timeout_secs,
+        } => run_command(command, *timeout_secs, cert).await,
+    }
+}
+
+//written next to the target and renamed over it once owner and mode are set, so the target is
+//never half written or briefly readable by the wrong users
+fn install(
+    contents: &[u8],
+    target: &Path,
+    owner: &Option<String>,
+    group: &Option<String>,
+    mode: &Option<String>,
+    private: bool,
+) -> Result<(), String> {
+    let mode = match mode {
+        Some(mode) => u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+            .ok()
+            .filter(|mode| *mode <= 0o7777)
+            .ok_or_else(|| format!("mode '{mode}' is not an octal permission like 0640"))?,
+        None if private => 0o600,
+        None => 0o644,
+    };
+    let uid = owner.as_deref().map(user_id).transpose()?;
+    let gid = group.as_deref().map(group_id).transpose()?;
+
+    let temporary = temporary_path(target);
+    let failed = |e: std::io::Error| format!("failed to write {}: {e}", temporary.display());
+
+    match fs::remove_file(&temporary) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(failed(e)),
+        _ => (),
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&temporary).map_err(failed)?;
+    file.write_all(contents).map_err(failed)?;
+    drop(file);
+
+    set_owner_and_mode(&temporary, uid, gid, mode).map_err(failed)?;
+    fs::rename(&temporary, target).map_err(|e| {
+        let _ = fs::remove_file(&temporary);
+        format!("failed to replace {}: {e}", target.display())
+    })
+}
+
+#[cfg(unix)]
+fn set_owner_and_mode(
+    path: &Path,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    mode: u32,
+) -> std::io::Result<()> {
+    use std::os::unix::fs::{chown, PermissionsExt};
+    if uid.is_some() || gid.is_some() {
+        chown(path, uid, gid)?;
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_owner_and_mode(
+    _path: &Path,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    _mode: u32,
+) -> std::io::Result<()> {
+    match uid.is_some() || gid.is_some() {
+        true => Err(std::io::Error::other(
+            "owner and group are only supported on unix",
+        )),
+        false => Ok(()),
+    }
+}
+
+//names are resolved through NSS, so users and groups from sssd or LDAP work the same as local ones
+#[cfg(unix)]
+fn resolve_id(
+    kind: &str,
+    name: &str,
+    lookup: impl Fn(&CStr, &mut [libc::c_char]) -> Result<Option<u32>, i32>,
+) -> Result<u32, String> {
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+    let c_name = CString::new(name).map_err(|_| format!("invalid {kind} name '{name}'"))?;
+    //grown until the entry fits, getpw/getgr report ERANGE when it doesn't
+    let mut buffer = vec![0; 1024];
+    loop {
+        match lookup(&c_name, &mut buffer) {
+            Ok(Some(id)) => return Ok(id),
+            Ok(None) => return Err(format!("no {kind} named '{name}'")),
+            Err(libc::ERANGE) if buffer.len() < 1 << 20 => buffer.resize(buffer.len() * 2, 0),
+            Err(e) => {
+                return Err(format!(
+                    "failed to look up {kind} '{name}': {}",
+                    std::io::Error::from_raw_os_error(e)
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn user_id(name: &str) -> Result<u32, String> {
+    resolve_id("user", name, |name, buffer| {
+        let mut entry = MaybeUninit::<libc::passwd>::uninit();
+        let mut found = ptr::null_mut();
+        //SAFETY: every pointer is valid for the call and buffer.len() is the real buffer size
+        let status = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                entry.as_mut_ptr(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut found,
+            )
+        };
+        match status {
+            0 if found.is_null() => Ok(None),
+            //SAFETY: a non null result means entry was filled in
+            0 => Ok(Some(unsafe { entry.assume_init() }.pw_uid)),
+            e => Err(e),
+        }
+    })
+}
+
+#[cfg(unix)]
+fn group_id(name: &str) -> Result<u32, String> {
+    resolve_id("group", name, |name, buffer| {
+        let mut entry = MaybeUninit::<libc::group>::uninit();
+        let mut found = ptr::null_mut();
+        //SAFETY: every pointer is valid for the call and buffer.len() is the real buffer size
+        let status = unsafe {
+            libc::getgrnam_r(
+                name.as_ptr(),
+                entry.as_mut_ptr(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut found,
+            )
+        };
+        match status {
+            0 if found.is_null() => Ok(None),
+            //SAFETY: a non null result means entry was filled in
+            0 => Ok(Some(unsafe { entry.assume_init() }.gr_gid)),
+            e => Err(e),
+        }
+    })
+}
+
+//set_owner_and_mode rejects owners and groups off unix anyway
+#[cfg(not(unix))]
+fn user_id(name: &str) -> Result<u32, String> {
+    name.parse()
+        .map_err(|_| format!("user '{name}' must be a numeric id on this platform"))
+}
+
+#[cfg(not(unix))]
+fn group_id(name: &str) -> Result<u32, String> {
+    name.parse()
+        .map_err(|_| format!("group '{name}' must be a numeric id on this platform"))
+}
+
+//the certificate paths are passed in the environment, so the command can use them
+async fn run_command(command: &str, timeout_secs: u64, cert: &DeployCert) -> Result<(), String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("DOMAIN", &cert.domain)
+        .env("KEYFILE", &cert.keyfile)
+        .env("CERTFILE", &cert.certfile)
+        .env("FULLCHAINFILE", &cert.fullchainfile)
+        .env("CHAINFILE", &cert.chainfile)
+        .env("TIMESTAMP", &cert.timestamp)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to start: {e}"))?;
+
+    match timeout(Duration::from_secs(timeout_secs), child.wait()).await {
+        Ok(Ok(status)) if status.success() => Ok(()),
+        Ok(Ok(status)) => Err(format!("exited with {status}")),
+        Ok(Err(e)) => Err(format!("failed to wait for it: {e}")),
+        Err(_) => {
+            let _ = child.kill().await;
+            Err(format!(
+                "still running after {timeout_secs} seconds, killed it"
+            ))
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_found_by_name() {
+        assert_eq!(user_id("root"), Ok(0));
+        assert_eq!(user_id("33"), Ok(33));
+        assert_eq!(group_id("33"), Ok(33));
+        assert!(user_id("no-such-user-linode-dns").is_err());
+        assert!(group_id("no-such-group-linode-dns").is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{HookError, PropagationConfig};
+use hickory_resolver::{
+    config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
+    error::ResolveError,
+    AsyncResolver,
+};
+use std::{net::SocketAddr, time::Duration};
+use tokio::time::{sleep, Instant};
+
+//Nameservers for every Linode hosted zone, used if the zone's NS set can't be looked up
+const LINODE_NAMESERVERS: [&str; 5] = [
+    "ns1.linode.com.",
+    "ns2.linode.com.",
+    "ns3.linode.com.",
+    "ns4.linode.com.",
+    "ns5.linode.com.",
+];
+
+//Finds the zone's NS set through the system resolver and returns an address for each server
+pub async fn authoritative_nameservers(zone: &str) -> Result<Vec<SocketAddr>, ResolveError> {
+    let resolver = AsyncResolver::tokio_from_system_conf()?;
+
+    let mut names: Vec<String> = match resolver.ns_lookup(format!("{zone}.")).await {
+        Ok(response) => response.iter().map(|name| name.to_string()).collect(),
+        Err(_) => Vec::new(),
+    };
+    if names.is_empty() {
+        names = LINODE_NAMESERVERS.map(str::to_owned).to_vec();
+    }
+
+    let mut nameservers = Vec::new();
+    for name in names {
+        let response = resolver.lookup_ip(name.as_str()).await?;
+        match response.iter().next() {
+            Some(ip) => nameservers.push(SocketAddr::new(ip, 53)),
+            None => Err(ResolveError::from(format!(
+                "No address found for nameserver {name}"
+            )))?,
+        }
+    }
+    Ok(nameservers)
+}
+
+//Succeeds only once every nameserver given answers with the text value
+pub async fn text_record_exists(
+    nameservers: &[SocketAddr],
+    domain: &str,
+    text_value: &str,
+) -> Result<(), ResolveError> {
+    for nameserver in nameservers {
+        let mut resolver_config = ResolverConfig::new();
+        resolver_config.add_name_server(NameServerConfig::new(*nameserver, Protocol::Udp));
+        let resolver = AsyncResolver::tokio(resolver_config, ResolverOpts::default());
+
+        let response = resolver.txt_lookup(format!("{domain}.")).await?;
+        if !response
+            .iter()
+            .any(|record| record.to_string() == text_value)
+        {
+            Err(ResolveError::from(format!(
+                "Did not find text value on {nameserver}"
+            )))?
+        }
+    }
+    Ok(())
+}
+
+//with backoff the interval doubles, up to max_poll_interval_secs unless it started out above that
+fn next_poll_interval(settings: &PropagationConfig, interval: Duration) -> Duration {
+    match settings.backoff {
+        true => {
+            let max_interval = Duration::from_secs(settings.max_poll_interval_secs);
+            interval.saturating_mul(2).min(max_interval.max(interval))
+        }
+        false => interval,
+    }
+}
+
+pub async fn wait_for_record_population(
+    settings: PropagationConfig,
+    nameservers: Vec<SocketAddr>,
+    domain: String,
+    value: String,
+) -> Result<(String, String), HookError> {
+    //wait for record to populate, or give up once the timeout has passed
+    //(a timeout too large to represent never passes)
+    let deadline = Instant::now().checked_add(Duration::from_secs(settings.timeout_secs));
+    let mut interval = Duration::from_secs(settings.poll_interval_secs);
+
+    sleep(Duration::from_secs(settings.initial_delay_secs)).await;
+    loop {
+        if text_record_exists(&nameservers, &domain, &value)
+            .await
+            .is_ok()
+        {
+            return Ok((domain, value));
+        }
+
+        let now = Instant::now();
+        if deadline.is_some_and(|deadline| now >= deadline) {
+            break;
+        }
+        sleep(deadline.map_or(interval, |deadline| interval.min(deadline - now))).await;
+        interval = next_poll_interval(&settings, interval);
+    }
+    Err(HookError::DnsTimeout {
+        names: vec![domain],
+        timeout_secs: settings.timeout_secs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let settings = PropagationConfig {
+            backoff: true,
+            max_poll_interval_secs: 50,
+            ..PropagationConfig::default()
+        };
+        let next = |secs| next_poll_interval(&settings, Duration::from_secs(secs)).as_secs();
+        assert_eq!(next(15), 30);
+        assert_eq!(next(30), 50);
+        assert_eq!(next(80), 80);
+        assert_eq!(next_poll_interval(&settings, Duration::MAX), Duration::MAX);
+
+        let settings = PropagationConfig {
+            backoff: false,
+            ..settings
+        };
+        assert_eq!(
+            next_poll_interval(&settings, Duration::from_secs(15)).as_secs(),
+            15
+        );
+    }
+
+    //paused time skips the initial delay and the resolver's own timeouts
+    #[tokio::test(start_paused = true)]
+    async fn propagation_gives_up_at_the_timeout() {
+        let settings = PropagationConfig {
+            timeout_secs: 0,
+            initial_delay_secs: 30,
+            ..PropagationConfig::default()
+        };
+        let started = Instant::now();
+
+        //TEST-NET-1, nothing answers there
+        let unroutable = SocketAddr::from(([192, 0, 2, 1], 53));
+        let result = wait_for_record_population(
+            settings,
+            vec![unroutable],
+            "_acme-challenge.example.com".to_owned(),
+            "abc".to_owned(),
+        )
+        .await;
+
+        assert!(matches!(result, Err(HookError::DnsTimeout { .. })));
+        assert!(started.elapsed() >= Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,228 @@
+use hickory_resolver::error::ResolveError;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::{error::Error, fmt};
+use tokio::task::JoinError;
+
+//Linode's error payload, see https://techdocs.akamai.com/linode-api/reference/errors
+#[derive(Deserialize, Default)]
+pub struct ApiErrors {
+    pub errors: Vec<ApiErrorDetail>,
+}
+
+//field is only present when the error relates to one of the submitted values
+#[derive(Deserialize, Debug)]
+pub struct ApiErrorDetail {
+    pub field: Option<String>,
+    pub reason: String,
+}
+
+//Everything that can make a hook fail, each kind maps to its own exit code (see README)
+#[derive(Debug)]
+pub enum HookError {
+    MalformedArgs(String),
+    Config(String),
+    ZoneNotFound(String),
+    ApiAuth {
+        request: String,
+        errors: Vec<ApiErrorDetail>,
+    },
+    ApiRateLimit {
+        request: String,
+        errors: Vec<ApiErrorDetail>,
+    },
+    ApiValidation {
+        request: String,
+        errors: Vec<ApiErrorDetail>,
+    },
+    Api {
+        request: String,
+        status: StatusCode,
+        errors: Vec<ApiErrorDetail>,
+    },
+    Http(reqwest::Error),
+    DnsTimeout {
+        names: Vec<String>,
+        timeout_secs: u64,
+    },
+    Dns(ResolveError),
+    Journal(String),
+    Deploy(String),
+    Task(JoinError),
+}
+
+impl HookError {
+    //sorts a failed API response into the matching error kind
+    pub fn from_response(request: String, status: StatusCode, errors: Vec<ApiErrorDetail>) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                HookError::ApiAuth { request, errors }
+            }
+            StatusCode::TOO_MANY_REQUESTS => HookError::ApiRateLimit { request, errors },
+            StatusCode::BAD_REQUEST => HookError::ApiValidation { request, errors },
+            status => HookError::Api {
+                request,
+                status,
+                errors,
+            },
+        }
+    }
+
+    //whether Linode turned the request down with a 4xx status
+    pub fn is_client_error(&self) -> bool {
+        match self {
+            HookError::ApiAuth { .. }
+            | HookError::ApiRateLimit { .. }
+            | HookError::ApiValidation { .. } => true,
+            HookError::Api { status, .. } => status.is_client_error(),
+            _ => false,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            HookError::Task(_) => 1,
+            HookError::MalformedArgs(_) => 2,
+            HookError::Config(_) => 3,
+            HookError::ZoneNotFound(_) => 4,
+            HookError::ApiAuth { .. } => 5,
+            HookError::ApiRateLimit { .. } => 6,
+            HookError::ApiValidation { .. } => 7,
+            HookError::Api { .. } | HookError::Http(_) => 8,
+            HookError::DnsTimeout { .. } => 9,
+            HookError::Dns(_) => 10,
+            HookError::Journal(_) => 11,
+            HookError::Deploy(_) => 12,
+        }
+    }
+}
+
+//"[field] reason; reason" as reported by Linode
+fn describe_api_errors(errors: &[ApiErrorDetail]) -> String {
+    if errors.is_empty() {
+        return "no error details returned".to_owned();
+    }
+    errors
+        .iter()
+        .map(|error| match &error.field {
+            Some(field) => format!("[{field}] {}", error.reason),
+            None => error.reason.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl fmt::Display for HookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookError::MalformedArgs(message) => write!(f, "Malformed hook arguments: {message}"),
+            HookError::Config(message) => write!(f, "Configuration error: {message}"),
+            HookError::ZoneNotFound(name) => {
+                write!(f, "No zone on this Linode account holds {name}")
+            }
+            HookError::ApiAuth { request, errors } => write!(
+                f,
+                "Linode API refused the token for {request} (check it is valid and has Domains read/write access): {}",
+                describe_api_errors(errors)
+            ),
+            HookError::ApiRateLimit { request, errors } => write!(
+                f,
+                "Linode API rate limit hit on {request}: {}",
+                describe_api_errors(errors)
+            ),
+            HookError::ApiValidation { request, errors } => write!(
+                f,
+                "Linode API rejected {request}: {}",
+                describe_api_errors(errors)
+            ),
+            HookError::Api {
+                request,
+                status,
+                errors,
+            } => write!(
+                f,
+                "Linode API returned {status} for {request}: {}",
+                describe_api_errors(errors)
+            ),
+            HookError::Http(e) => write!(f, "Request to the Linode API failed: {e}"),
+            HookError::DnsTimeout {
+                names,
+                timeout_secs,
+            } => write!(
+                f,
+                "Gave up after {timeout_secs} seconds waiting for TXT records to reach every nameserver: {}",
+                names.join(", ")
+            ),
+            HookError::Dns(e) => write!(f, "DNS lookup failed: {e}"),
+            HookError::Journal(message) => write!(f, "Record journal error: {message}"),
+            HookError::Deploy(message) => write!(f, "Certificate deployment failed: {message}"),
+            HookError::Task(e) => write!(f, "Background task failed: {e}"),
+        }
+    }
+}
+
+impl Error for HookError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HookError::Http(e) => Some(e),
+            HookError::Dns(e) => Some(e),
+            HookError::Task(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for HookError {
+    fn from(e: reqwest::Error) -> Self {
+        HookError::Http(e)
+    }
+}
+
+impl From<ResolveError> for HookError {
+    fn from(e: ResolveError) -> Self {
+        HookError::Dns(e)
+    }
+}
+
+impl From<JoinError> for HookError {
+    fn from(e: JoinError) -> Self {
+        HookError::Task(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_failures_map_to_distinct_exit_codes() {
+        let code = |status| HookError::from_response(String::new(), status, Vec::new()).exit_code();
+        assert_eq!(code(StatusCode::UNAUTHORIZED), 5);
+        assert_eq!(code(StatusCode::FORBIDDEN), 5);
+        assert_eq!(code(StatusCode::TOO_MANY_REQUESTS), 6);
+        assert_eq!(code(StatusCode::BAD_REQUEST), 7);
+        assert_eq!(code(StatusCode::INTERNAL_SERVER_ERROR), 8);
+    }
+
+    #[test]
+    fn api_error_details_are_shown() {
+        let error = HookError::from_response(
+            "POST /domains/1/records".to_owned(),
+            StatusCode::BAD_REQUEST,
+            vec![
+                ApiErrorDetail {
+                    field: Some("target".to_owned()),
+                    reason: "Invalid target".to_owned(),
+                },
+                ApiErrorDetail {
+                    field: None,
+                    reason: "Try again".to_owned(),
+                },
+            ],
+        );
+        assert_eq!(
+            error.to_string(),
+            "Linode API rejected POST /domains/1/records: [target] Invalid target; Try again"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use crate::{cache::unix_time, HookError, LinodeDnsClient, Record};
+
+//Linode timestamps are UTC without a zone, e.g. 2018-01-01T00:01:01
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (date, time) = timestamp.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let mut time = time
+        .splitn(3, ':')
+        .map(|part| part.get(..2)?.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    //days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    u64::try_from(days * 86400 + hour * 3600 + minute * 60 + second).ok()
+}
+
+//challenge records are _acme-challenge at the zone apex or _acme-challenge.<subdomain>
+fn is_challenge_record(record: &Record) -> bool {
+    record.r#type == "TXT"
+        && (record.name == "_acme-challenge" || record.name.starts_with("_acme-challenge."))
+}
+
+impl LinodeDnsClient {
+    //Deletes _acme-challenge TXT records in every zone that haven't changed for max_age_hours,
+    //left behind when a run died between deploy_challenge and clean_challenge
+    pub async fn collect_garbage(&self, max_age_hours: u64) -> Result<(), HookError> {
+        let now = unix_time();
+        let cutoff = now.saturating_sub(max_age_hours.saturating_mul(3600));
+
+        let mut first_error = None;
+        for domain in self.get_domains().await? {
+            for record in self.get_records(domain.id).await? {
+                //updated covers records whose target was changed since creation
+                let changed = record.updated.as_deref().or(record.created.as_deref());
+                let Some(changed) = changed.and_then(parse_timestamp) else {
+                    continue;
+                };
+                if !is_challenge_record(&record) || changed > cutoff {
+                    continue;
+                }
+
+                let name = format!("{}.{}", record.name, domain.domain);
+                if self.dry_run {
+                    println!(
+                        "Would remove token '{}' for '{name}' - id:{} ({} hours old)",
+                        record.target,
+                        record.id,
+                        (now - changed) / 3600
+                    );
+                    continue;
+                }
+
+                match self
+                    .remove_and_report(domain.id, record.id, &name, &record.target)
+                    .await
+                {
+                    Ok(_) => self.update_journal(|journal| journal.forget(domain.id, record.id))?,
+                    Err(e) => {
+                        first_error.get_or_insert(e);
+                    }
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_convert_to_unix_seconds() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00"), Some(0));
+        assert_eq!(parse_timestamp("2018-01-01T00:01:01"), Some(1514764861));
+        assert_eq!(parse_timestamp("2024-02-29T23:59:59"), Some(1709251199));
+        assert_eq!(parse_timestamp("2024-07-01T12:00:00.123"), Some(1719835200));
+    }
+
+    #[test]
+    fn malformed_timestamps_are_rejected() {
+        assert_eq!(parse_timestamp("2018-01-01"), None);
+        assert_eq!(parse_timestamp("2018-13-01T00:00:00"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+}
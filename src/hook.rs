@@ -0,0 +1,330 @@
+use crate::HookError;
+use std::path::PathBuf;
+
+//Arguments as passed by dehydrated, see https://github.com/dehydrated-io/dehydrated/blob/master/docs/examples/hook.sh
+
+//One dns-01 challenge: the domain being validated and the TXT value it expects
+pub struct Challenge {
+    pub domain: String,
+    pub token_filename: String,
+    pub token_value: String,
+}
+
+pub struct DeployCert {
+    pub domain: String,
+    pub keyfile: PathBuf,
+    pub certfile: PathBuf,
+    pub fullchainfile: PathBuf,
+    pub chainfile: PathBuf,
+    pub timestamp: String,
+}
+
+pub struct UnchangedCert {
+    pub domain: String,
+    pub keyfile: PathBuf,
+    pub certfile: PathBuf,
+    pub fullchainfile: PathBuf,
+    pub chainfile: PathBuf,
+}
+
+pub struct InvalidChallenge {
+    pub domain: String,
+    pub response: String,
+}
+
+//An ACME request dehydrated made failed, headers are the raw response headers
+pub struct RequestFailure {
+    pub status_code: String,
+    pub reason: String,
+    pub request_type: String,
+    pub headers: String,
+}
+
+//Not a dehydrated hook: `gc [--max-age HOURS] [--dry-run]` run by hand or from cron,
+//max_age_hours falls back to gc_max_age_hours from the config, dry_run is the same as the global --dry-run
+pub struct GarbageCollect {
+    pub max_age_hours: Option<u64>,
+    pub dry_run: bool,
+}
+
+pub enum HookCommand {
+    //with HOOK_CHAIN=yes dehydrated passes every challenge of the certificate in one call
+    DeployChallenge(Vec<Challenge>),
+    CleanChallenge(Vec<Challenge>),
+    SyncCert,
+    DeployCert(DeployCert),
+    UnchangedCert(UnchangedCert),
+    InvalidChallenge(InvalidChallenge),
+    RequestFailure(RequestFailure),
+    GenerateCsr,
+    StartupHook,
+    ExitHook(Option<String>),
+    //dehydrated calls this_hookscript_is_broken__... to check unknown hooks exit 0 without output
+    BrokenHookTest,
+    GarbageCollect(GarbageCollect),
+    //hooks added to dehydrated after this was written, which must be ignored
+    Unknown(String),
+}
+
+//the hook's own arguments, after checking there are at least as many as it needs
+fn hook_args<'a>(
+    hook: &str,
+    args: &'a [String],
+    names: &[&str],
+) -> Result<&'a [String], HookError> {
+    if args.len() < names.len() {
+        return Err(HookError::MalformedArgs(format!(
+            "{hook} expects {}, got {} argument(s)",
+            names.join(" "),
+            args.len()
+        )));
+    }
+    Ok(args)
+}
+
+fn parse_gc(args: &[String]) -> Result<GarbageCollect, HookError> {
+    let mut gc = GarbageCollect {
+        max_age_hours: None,
+        dry_run: false,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => gc.dry_run = true,
+            "--max-age" => {
+                let hours = args.next().and_then(|hours| hours.parse().ok());
+                if hours.is_none() {
+                    return Err(HookError::MalformedArgs(
+                        "gc --max-age expects a whole number of hours".to_owned(),
+                    ));
+                }
+                gc.max_age_hours = hours;
+            }
+            unknown => {
+                return Err(HookError::MalformedArgs(format!(
+                    "gc accepts --max-age HOURS and --dry-run, got {unknown}"
+                )))
+            }
+        }
+    }
+    Ok(gc)
+}
+
+fn parse_challenges(hook: &str, args: &[String]) -> Result<Vec<Challenge>, HookError> {
+    if args.is_empty() || !args.len().is_multiple_of(3) {
+        return Err(HookError::MalformedArgs(format!(
+            "{hook} expects DOMAIN TOKEN_FILENAME TOKEN_VALUE triples, got {} argument(s)",
+            args.len()
+        )));
+    }
+    Ok(args
+        .chunks(3)
+        .map(|x| Challenge {
+            domain: x[0].to_owned(),
+            token_filename: x[1].to_owned(),
+            token_value: x[2].to_owned(),
+        })
+        .collect())
+}
+
+impl HookCommand {
+    //args as given to the hook, starting with the hook name (so without the program name)
+    pub fn parse(args: &[String]) -> Result<Self, HookError> {
+        let Some((hook, args)) = args.split_first() else {
+            return Err(HookError::MalformedArgs("no hook name given".to_owned()));
+        };
+
+        let command = match hook.as_str() {
+            "deploy_challenge" => HookCommand::DeployChallenge(parse_challenges(hook, args)?),
+            "clean_challenge" => HookCommand::CleanChallenge(parse_challenges(hook, args)?),
+            "sync_cert" => HookCommand::SyncCert,
+            "deploy_cert" => {
+                let args = hook_args(
+                    hook,
+                    args,
+                    &[
+                        "DOMAIN",
+                        "KEYFILE",
+                        "CERTFILE",
+                        "FULLCHAINFILE",
+                        "CHAINFILE",
+                        "TIMESTAMP",
+                    ],
+                )?;
+                HookCommand::DeployCert(DeployCert {
+                    domain: args[0].to_owned(),
+                    keyfile: PathBuf::from(&args[1]),
+                    certfile: PathBuf::from(&args[2]),
+                    fullchainfile: PathBuf::from(&args[3]),
+                    chainfile: PathBuf::from(&args[4]),
+                    timestamp: args[5].to_owned(),
+                })
+            }
+            "unchanged_cert" => {
+                let args = hook_args(
+                    hook,
+                    args,
+                    &[
+                        "DOMAIN",
+                        "KEYFILE",
+                        "CERTFILE",
+                        "FULLCHAINFILE",
+                        "CHAINFILE",
+                    ],
+                )?;
+                HookCommand::UnchangedCert(UnchangedCert {
+                    domain: args[0].to_owned(),
+                    keyfile: PathBuf::from(&args[1]),
+                    certfile: PathBuf::from(&args[2]),
+                    fullchainfile: PathBuf::from(&args[3]),
+                    chainfile: PathBuf::from(&args[4]),
+                })
+            }
+            "invalid_challenge" => {
+                let args = hook_args(hook, args, &["DOMAIN", "RESPONSE"])?;
+                HookCommand::InvalidChallenge(InvalidChallenge {
+                    domain: args[0].to_owned(),
+                    response: args[1].to_owned(),
+                })
+            }
+            "request_failure" => {
+                let args = hook_args(hook, args, &["STATUSCODE", "REASON", "REQTYPE"])?;
+                HookCommand::RequestFailure(RequestFailure {
+                    status_code: args[0].to_owned(),
+                    reason: args[1].to_owned(),
+                    request_type: args[2].to_owned(),
+                    headers: args.get(3).cloned().unwrap_or_default(),
+                })
+            }
+            "generate_csr" => HookCommand::GenerateCsr,
+            "startup_hook" => HookCommand::StartupHook,
+            "exit_hook" => HookCommand::ExitHook(args.first().cloned()),
+            "gc" => HookCommand::GarbageCollect(parse_gc(args)?),
+            test if test.starts_with("this_hookscript_is_broken") => HookCommand::BrokenHookTest,
+            unknown => HookCommand::Unknown(unknown.to_owned()),
+        };
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<HookCommand, HookError> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        HookCommand::parse(&args)
+    }
+
+    #[test]
+    fn challenges_are_grouped_in_triples() {
+        let command = parse(&[
+            "deploy_challenge",
+            "example.com",
+            "file1",
+            "value1",
+            "www.example.com",
+            "file2",
+            "value2",
+        ]);
+        let Ok(HookCommand::DeployChallenge(challenges)) = command else {
+            panic!("expected deploy_challenge");
+        };
+        assert_eq!(challenges.len(), 2);
+        assert_eq!(challenges[1].domain, "www.example.com");
+        assert_eq!(challenges[1].token_filename, "file2");
+        assert_eq!(challenges[1].token_value, "value2");
+    }
+
+    #[test]
+    fn partial_challenge_is_rejected() {
+        let command = parse(&[
+            "clean_challenge",
+            "example.com",
+            "file1",
+            "value1",
+            "www.example.com",
+        ]);
+        assert!(matches!(command, Err(HookError::MalformedArgs(_))));
+        assert!(matches!(
+            parse(&["deploy_challenge"]),
+            Err(HookError::MalformedArgs(_))
+        ));
+    }
+
+    #[test]
+    fn missing_cert_arguments_are_rejected() {
+        let command = parse(&["deploy_cert", "example.com", "privkey.pem"]);
+        let Err(HookError::MalformedArgs(message)) = command else {
+            panic!("expected malformed arguments");
+        };
+        assert!(message.contains("FULLCHAINFILE"));
+        assert!(matches!(
+            parse(&["invalid_challenge", "example.com"]),
+            Err(HookError::MalformedArgs(_))
+        ));
+    }
+
+    #[test]
+    fn request_failure_headers_are_optional() {
+        let command = parse(&["request_failure", "503", "Service Unavailable", "NEW_ORDER"]);
+        let Ok(HookCommand::RequestFailure(failure)) = command else {
+            panic!("expected request_failure");
+        };
+        assert_eq!(failure.status_code, "503");
+        assert_eq!(failure.request_type, "NEW_ORDER");
+        assert_eq!(failure.headers, "");
+
+        let command = parse(&[
+            "request_failure",
+            "429",
+            "Too Many Requests",
+            "NEW_ORDER",
+            "Retry-After: 60",
+        ]);
+        let Ok(HookCommand::RequestFailure(failure)) = command else {
+            panic!("expected request_failure");
+        };
+        assert_eq!(failure.headers, "Retry-After: 60");
+
+        let command = parse(&["request_failure", "503", "Service Unavailable"]);
+        let Err(HookError::MalformedArgs(message)) = command else {
+            panic!("expected malformed arguments");
+        };
+        assert!(message.contains("REQTYPE"));
+    }
+
+    #[test]
+    fn unknown_hooks_are_passed_through() {
+        assert!(matches!(
+            parse(&["some_future_hook", "arg"]),
+            Ok(HookCommand::Unknown(name)) if name == "some_future_hook"
+        ));
+        assert!(matches!(
+            parse(&["exit_hook"]),
+            Ok(HookCommand::ExitHook(None))
+        ));
+        assert!(matches!(
+            parse(&["this_hookscript_is_broken__dehydrated_is_testing_for_correct_error_handling"]),
+            Ok(HookCommand::BrokenHookTest)
+        ));
+    }
+
+    #[test]
+    fn gc_options_are_parsed() {
+        let Ok(HookCommand::GarbageCollect(gc)) = parse(&["gc", "--dry-run", "--max-age", "48"])
+        else {
+            panic!("expected gc");
+        };
+        assert_eq!(gc.max_age_hours, Some(48));
+        assert!(gc.dry_run);
+        assert!(matches!(
+            parse(&["gc", "--max-age", "a day"]),
+            Err(HookError::MalformedArgs(_))
+        ));
+        assert!(matches!(
+            parse(&["gc", "--force"]),
+            Err(HookError::MalformedArgs(_))
+        ));
+    }
+}
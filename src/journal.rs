@@ -0,0 +1,151 @@
+use crate::{AddedRecord, HookError, LinodeDnsClient};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+//<path>.tmp, where files are written before being renamed over path
+pub(crate) fn temporary_path(path: &Path) -> PathBuf {
+    let mut temporary = OsString::from(path);
+    temporary.push(".tmp");
+    PathBuf::from(temporary)
+}
+
+//Records deploy_challenge created that haven't been cleaned up yet, kept on disk between hook calls
+//so they can still be removed if the run fails part way through
+#[derive(Serialize, Deserialize, Default)]
+pub struct RecordJournal {
+    pub records: Vec<AddedRecord>,
+}
+
+impl RecordJournal {
+    //a missing journal is an empty one
+    pub fn load(path: &Path) -> Result<Self, HookError> {
+        match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).map_err(|e| {
+                HookError::Journal(format!("Failed to parse {}: {e}", path.display()))
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(RecordJournal::default()),
+            Err(e) => Err(HookError::Journal(format!(
+                "Failed to read {}: {e}",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn find(&self, name: &str, token: &str) -> Option<&AddedRecord> {
+        self.records
+            .iter()
+            .find(|record| record.name == name && record.token == token)
+    }
+
+    //drops a record once it has been deleted
+    pub fn forget(&mut self, domain_id: i32, record_id: i32) {
+        self.records
+            .retain(|record| (record.domain_id, record.record_id) != (domain_id, record_id));
+    }
+
+    //written to a temporary file first so a crash can't leave a half written journal
+    pub fn save(&self, path: &Path) -> Result<(), HookError> {
+        let write_error = |e: std::io::Error| {
+            HookError::Journal(format!("Failed to write {}: {e}", path.display()))
+        };
+
+        if self.records.is_empty() {
+            return match fs::remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(write_error(e)),
+                _ => Ok(()),
+            };
+        }
+
+        let temporary = temporary_path(path);
+        let contents = serde_json::to_string_pretty(self).expect("journal always serializes");
+        fs::write(&temporary, contents).map_err(write_error)?;
+        fs::rename(&temporary, path).map_err(write_error)
+    }
+}
+
+impl LinodeDnsClient {
+    //an empty journal when none is configured or nothing has been written yet
+    pub(crate) fn load_journal(&self) -> Result<RecordJournal, HookError> {
+        match &self.journal {
+            Some(path) => RecordJournal::load(path),
+            None => Ok(RecordJournal::default()),
+        }
+    }
+
+    //load, change and save the journal, doing nothing when no journal is configured or in a dry run
+    pub(crate) fn update_journal(
+        &self,
+        change: impl FnOnce(&mut RecordJournal),
+    ) -> Result<(), HookError> {
+        let Some(path) = self.journal.as_ref().filter(|_| !self.dry_run) else {
+            return Ok(());
+        };
+        let mut journal = RecordJournal::load(path)?;
+        change(&mut journal);
+        journal.save(path)
+    }
+
+    //deletes a record and reports how it went, one that is already gone counts as removed
+    pub(crate) async fn remove_and_report(
+        &self,
+        domain_id: i32,
+        record_id: i32,
+        name: &str,
+        token: &str,
+    ) -> Result<(), HookError> {
+        match self.remove_txt_record(domain_id, record_id).await {
+            Ok(_) if self.dry_run => (),
+            Ok(_) => println!("Removed token '{token}' for '{name}' - id:{record_id}"),
+            //deleted by hand or an earlier run, there's nothing left to remove
+            Err(HookError::Api {
+                status: StatusCode::NOT_FOUND,
+                ..
+            }) => println!("Token '{token}' for '{name}' - id:{record_id} was already removed"),
+            Err(e) => {
+                println!("FAILED to remove token '{token}' for '{name}' - id:{record_id} ({e})");
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    //deletes every record still in the journal, leaving any that failed to delete for next time
+    pub async fn remove_journaled_records(&self) -> Result<(), HookError> {
+        let Some(path) = &self.journal else {
+            println!("No record journal configured, nothing to clean up");
+            return Ok(());
+        };
+        let journal = RecordJournal::load(path)?;
+
+        let mut remaining = RecordJournal::default();
+        let mut first_error = None;
+        for record in journal.records {
+            if let Err(e) = self
+                .remove_and_report(
+                    record.domain_id,
+                    record.record_id,
+                    &record.name,
+                    &record.token,
+                )
+                .await
+            {
+                remaining.records.push(record);
+                first_error.get_or_insert(e);
+            }
+        }
+        if !self.dry_run {
+            remaining.save(path)?;
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
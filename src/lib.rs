@@ -0,0 +1,33 @@
+//Linode DNS operations and the dehydrated dns-01 challenge workflow, shared by the hook binary
+//and anything else that needs to manage Linode hosted records
+mod api;
+mod cache;
+mod challenge;
+mod config;
+mod deploy;
+mod dns;
+mod error;
+mod gc;
+mod hook;
+mod journal;
+mod nodebalancer;
+mod zone;
+
+pub use api::{
+    check_response, new_connection, AddedRecord, Domain, Domains, LinodeDnsClient, Page, Record,
+    Records, TextRecordInsert, TextRecordResult,
+};
+pub use cache::ZoneCache;
+pub use config::{
+    load_api_token, CertFile, Config, DeployAction, PropagationConfig, RecordConfig, RetryConfig,
+};
+pub use deploy::run_deploy_actions;
+pub use dns::{authoritative_nameservers, text_record_exists, wait_for_record_population};
+pub use error::{ApiErrorDetail, ApiErrors, HookError};
+pub use hook::{
+    Challenge, DeployCert, GarbageCollect, HookCommand, InvalidChallenge, RequestFailure,
+    UnchangedCert,
+};
+pub use journal::RecordJournal;
+pub use nodebalancer::{certificate_fingerprint, NodeBalancerCertificate, NodeBalancerConfig};
+pub use zone::find_zone;
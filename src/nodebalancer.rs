@@ -0,0 +1,127 @@
+use crate::{HookError, LinodeDnsClient};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+use serde::{Deserialize, Serialize};
+
+//Structure fields as determined by https://techdocs.akamai.com/linode-api/reference/put-node-balancer-config
+#[derive(Serialize)]
+pub struct NodeBalancerCertificate {
+    pub ssl_cert: String,
+    pub ssl_key: String,
+}
+
+//Linode never returns the certificate or key themselves, only what identifies them
+#[derive(Deserialize)]
+pub struct NodeBalancerConfig {
+    pub protocol: String,
+    pub ssl_fingerprint: String,
+}
+
+//SHA-1 of the first certificate in a PEM file as colon separated hex, the form Linode reports it in
+pub fn certificate_fingerprint(pem: &str) -> Option<String> {
+    let (_, rest) = pem.split_once("-----BEGIN CERTIFICATE-----")?;
+    let (body, _) = rest.split_once("-----END CERTIFICATE-----")?;
+    let body: String = body.split_whitespace().collect();
+    let der = STANDARD.decode(body).ok()?;
+
+    let hash = digest(&SHA1_FOR_LEGACY_USE_ONLY, &der);
+    let hex: Vec<String> = hash.as_ref().iter().map(|b| format!("{b:02X}")).collect();
+    Some(hex.join(":"))
+}
+
+fn same_fingerprint(a: &str, b: &str) -> bool {
+    let normalize = |fingerprint: &str| fingerprint.replace(':', "").to_ascii_uppercase();
+    normalize(a) == normalize(b)
+}
+
+impl LinodeDnsClient {
+    pub async fn get_nodebalancer_config(
+        &self,
+        nodebalancer_id: i32,
+        config_id: i32,
+    ) -> Result<NodeBalancerConfig, HookError> {
+        let url = format!(
+            "{}/nodebalancers/{nodebalancer_id}/configs/{config_id}",
+            self.base_url
+        );
+        let request = self.client.get(&url);
+        Ok(self
+            .send(request, &format!("GET {url}"))
+            .await?
+            .json()
+            .await?)
+    }
+
+    //replaces the certificate and key of an https config, then reads the config back to check it is
+    //serving the new one. fullchain may hold the whole chain, only its first certificate is compared
+    pub async fn upload_nodebalancer_certificate(
+        &self,
+        nodebalancer_id: i32,
+        config_id: i32,
+        fullchain: &str,
+        key: &str,
+    ) -> Result<(), HookError> {
+        let expected = certificate_fingerprint(fullchain).ok_or_else(|| {
+            HookError::Deploy("the certificate to upload is not a PEM certificate".to_owned())
+        })?;
+
+        //other protocols have nowhere to put a certificate
+        let config = self
+            .get_nodebalancer_config(nodebalancer_id, config_id)
+            .await?;
+        if config.protocol != "https" {
+            return Err(HookError::Deploy(format!(
+                "NodeBalancer {nodebalancer_id} config {config_id} uses protocol '{}', a certificate can only be uploaded to an https config",
+                config.protocol
+            )));
+        }
+
+        let url = format!(
+            "{}/nodebalancers/{nodebalancer_id}/configs/{config_id}",
+            self.base_url
+        );
+        if self.dry_run {
+            println!("[dry run] PUT {url} (certificate {expected})");
+            return Ok(());
+        }
+
+        let certificate = NodeBalancerCertificate {
+            ssl_cert: fullchain.to_owned(),
+            ssl_key: key.to_owned(),
+        };
+        let request = self.client.put(&url).json(&certificate);
+        self.send(request, &format!("PUT {url}")).await?;
+
+        let config = self
+            .get_nodebalancer_config(nodebalancer_id, config_id)
+            .await?;
+        if !same_fingerprint(&config.ssl_fingerprint, &expected) {
+            return Err(HookError::Deploy(format!(
+                "NodeBalancer {nodebalancer_id} config {config_id} reports certificate '{}' after the update, expected {expected}",
+                config.ssl_fingerprint
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_is_taken_from_the_first_certificate() {
+        let pem = "-----BEGIN CERTIFICATE-----\naGVs\nbG8=\n-----END CERTIFICATE-----\n\
+                   -----BEGIN CERTIFICATE-----\nd29ybGQ=\n-----END CERTIFICATE-----\n";
+        let fingerprint = certificate_fingerprint(pem).unwrap();
+        assert_eq!(
+            fingerprint,
+            "AA:F4:C6:1D:DC:C5:E8:A2:DA:BE:DE:0F:3B:48:2C:D9:AE:A9:43:4D"
+        );
+        assert!(same_fingerprint(
+            &fingerprint,
+            "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"
+        ));
+        assert_eq!(certificate_fingerprint("not a certificate"), None);
+    }
+}
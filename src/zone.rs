@@ -0,0 +1,98 @@
+use crate::Domain;
+
+//Picks the most specific zone holding domain_name (so sub.example.com wins over example.com),
+//returning the subdomain part relative to that zone. Case and trailing dots are ignored
+pub fn find_zone<'a>(domains: &'a [Domain], domain_name: &str) -> Option<(String, &'a Domain)> {
+    let name = domain_name.trim_end_matches('.');
+    let lower_name = name.to_ascii_lowercase();
+
+    domains
+        .iter()
+        .filter_map(|entry| {
+            let zone = entry.domain.trim_end_matches('.').to_ascii_lowercase();
+            if lower_name == zone {
+                return Some((String::new(), entry, zone.len()));
+            }
+            let prefix = lower_name.strip_suffix(&zone)?.strip_suffix('.')?;
+            Some((name[..prefix.len()].to_owned(), entry, zone.len()))
+        })
+        .max_by_key(|(_, _, zone_length)| *zone_length)
+        .map(|(subdomain, entry, _)| (subdomain, entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Domains, Page};
+
+    fn domains(names: &[&str]) -> Domains {
+        let data: Vec<Domain> = names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| Domain {
+                id: id as i32 + 1,
+                domain: name.to_string(),
+            })
+            .collect();
+        let results = data.len() as u32;
+        Page {
+            data,
+            page: 1,
+            pages: 1,
+            results,
+        }
+    }
+
+    fn zone_for(domains: &Domains, name: &str) -> Option<(String, i32)> {
+        find_zone(&domains.data, name).map(|(subdomain, entry)| (subdomain, entry.id))
+    }
+
+    #[test]
+    fn find_zone_prefers_longest_match_regardless_of_order() {
+        let forward = domains(&["example.com", "sub.example.com"]);
+        let reverse = domains(&["sub.example.com", "example.com"]);
+
+        let name = "_acme-challenge.www.sub.example.com";
+        assert_eq!(
+            zone_for(&forward, name),
+            Some(("_acme-challenge.www".to_owned(), 2))
+        );
+        assert_eq!(
+            zone_for(&reverse, name),
+            Some(("_acme-challenge.www".to_owned(), 1))
+        );
+
+        let name = "_acme-challenge.example.com";
+        assert_eq!(
+            zone_for(&forward, name),
+            Some(("_acme-challenge".to_owned(), 1))
+        );
+        assert_eq!(
+            zone_for(&reverse, name),
+            Some(("_acme-challenge".to_owned(), 2))
+        );
+    }
+
+    #[test]
+    fn find_zone_matches_apex() {
+        let list = domains(&["example.com", "sub.example.com"]);
+        assert_eq!(zone_for(&list, "sub.example.com"), Some((String::new(), 2)));
+    }
+
+    #[test]
+    fn find_zone_ignores_case_and_trailing_dots() {
+        let list = domains(&["Example.COM."]);
+        assert_eq!(
+            zone_for(&list, "_acme-challenge.WWW.example.com."),
+            Some(("_acme-challenge.WWW".to_owned(), 1))
+        );
+        assert_eq!(zone_for(&list, "EXAMPLE.com"), Some((String::new(), 1)));
+    }
+
+    #[test]
+    fn find_zone_requires_label_boundary() {
+        let list = domains(&["example.com"]);
+        assert_eq!(zone_for(&list, "_acme-challenge.notexample.com"), None);
+        assert_eq!(zone_for(&list, "example.org"), None);
+    }
+}
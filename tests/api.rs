@@ -0,0 +1,398 @@
+mod common;
+
+use common::{page, query_page, zone_server, zone_server_with, MockServer};
+use linode_dns::{Challenge, HookError, LinodeDnsClient, RecordConfig};
+use reqwest::StatusCode;
+use std::sync::{Arc, Mutex};
+
+#[tokio::test]
+async fn get_domains_walks_every_page() {
+    let server = MockServer::start(|request| {
+        let number = query_page(&request.path);
+        let domain = format!(r#"{{"id":{},"domain":"example{number}.com"}}"#, number * 10);
+        (200, page(&domain, number, 3))
+    });
+
+    let domains = server.client().get_domains().await.unwrap();
+
+    let ids: Vec<i32> = domains.iter().map(|domain| domain.id).collect();
+    assert_eq!(ids, [10, 20, 30]);
+    assert_eq!(domains[2].domain, "example3.com");
+    assert_eq!(
+        server.paths(),
+        [
+            "/v4/domains?page=1&page_size=500",
+            "/v4/domains?page=2&page_size=500",
+            "/v4/domains?page=3&page_size=500",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn get_records_handles_empty_listing() {
+    let server = MockServer::start(|_| (200, page("", 1, 0)));
+
+    let records = server.client().get_records(1).await.unwrap();
+
+    assert!(records.is_empty());
+    assert_eq!(
+        server.paths(),
+        ["/v4/domains/1/records?page=1&page_size=500"]
+    );
+}
+
+#[tokio::test]
+async fn api_errors_are_read_from_failed_responses() {
+    let server =
+        MockServer::start(|_| (401, r#"{"errors":[{"reason":"Invalid Token"}]}"#.to_owned()));
+
+    let error = server.client().get_domains().await.err().unwrap();
+
+    assert_eq!(error.exit_code(), 5);
+    assert!(error.to_string().ends_with(": Invalid Token"));
+}
+
+#[tokio::test]
+async fn non_json_error_bodies_still_report_status() {
+    let server = MockServer::start(|_| (404, "<html>Not Found</html>".to_owned()));
+
+    let error = server.client().get_domains().await.err().unwrap();
+
+    assert!(matches!(
+        error,
+        HookError::Api {
+            status: StatusCode::NOT_FOUND,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn rate_limited_requests_are_retried() {
+    let calls = Arc::new(Mutex::new(0));
+    let counter = calls.clone();
+    let server = MockServer::start(move |_| {
+        let mut calls = counter.lock().unwrap();
+        *calls += 1;
+        match *calls {
+            1 => (
+                429,
+                r#"{"errors":[{"reason":"Too Many Requests"}]}"#.to_owned(),
+            ),
+            2 => (503, String::new()),
+            _ => (200, page("", 1, 1)),
+        }
+    });
+
+    server.client().get_domains().await.unwrap();
+
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn retries_stop_after_max_attempts() {
+    let server = MockServer::start(|_| (500, String::new()));
+
+    let error = server.client().get_domains().await.err().unwrap();
+
+    assert_eq!(error.exit_code(), 8);
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn failed_record_creation_is_not_retried() {
+    let server = zone_server_with("", |_| (503, String::new()));
+
+    let error = server
+        .client()
+        .add_txt_record("_acme-challenge.www.example.com", "abc")
+        .await
+        .err()
+        .unwrap();
+
+    assert_eq!(error.exit_code(), 8);
+    let posts = server
+        .requests()
+        .iter()
+        .filter(|request| request.method == "POST")
+        .count();
+    assert_eq!(posts, 1);
+}
+
+#[tokio::test]
+async fn domain_and_record_lookups_use_configured_base_url() {
+    let server = MockServer::start(|request| {
+        let body = match request.path.split('?').next().unwrap() {
+            "/v4/domains" => page(r#"{"id":7,"domain":"example.com"}"#, 1, 1),
+            "/v4/domains/7/records" => page(
+                r#"{"id":70,"type":"TXT","name":"_acme-challenge.www","target":"abc"}"#,
+                1,
+                1,
+            ),
+            _ => return (404, r#"{"errors":[{"reason":"Not found"}]}"#.to_owned()),
+        };
+        (200, body)
+    });
+    let client = server.client();
+
+    let (subdomain, zone, domain_id) = client.get_domain_info("www.example.com").await.unwrap();
+    assert_eq!(
+        (subdomain.as_str(), zone.as_str(), domain_id),
+        ("www", "example.com", 7)
+    );
+
+    let record_id = client
+        .get_record_id(domain_id, &subdomain, "abc")
+        .await
+        .unwrap();
+    assert_eq!(record_id, Some(70));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn add_and_remove_txt_record() {
+    let server = zone_server("");
+    let client = server.client();
+
+    let record = client
+        .add_txt_record("_acme-challenge.www.example.com", "abc")
+        .await
+        .unwrap();
+    assert_eq!((record.domain_id, record.record_id), (7, 71));
+    assert_eq!(record.zone, "example.com");
+
+    client.remove_txt_record(7, 71).await.unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests[2].method, "POST");
+    assert_eq!(requests[2].path, "/v4/domains/7/records");
+    assert_eq!(
+        requests[2].body,
+        r#"{"type":"TXT","name":"_acme-challenge.www","target":"abc","ttl_sec":30}"#
+    );
+    assert_eq!(requests[3].method, "DELETE");
+    assert_eq!(requests[3].path, "/v4/domains/7/records/71");
+}
+
+#[tokio::test]
+async fn dry_run_only_reads() {
+    let server =
+        zone_server(r#"{"id":71,"type":"TXT","name":"_acme-challenge.www","target":"abc"}"#);
+    let mut config = server.config();
+    config.dry_run = true;
+    let client = LinodeDnsClient::new(&config).unwrap();
+
+    let challenges = [Challenge {
+        domain: "www.example.com".to_owned(),
+        token_filename: "file".to_owned(),
+        token_value: "abc".to_owned(),
+    }];
+    //the existing record is reused and there is no propagation to wait for, the domain list is
+    //only fetched once between the two
+    client.deploy_challenge(&challenges).await.unwrap();
+    client.clean_challenge(&challenges).await.unwrap();
+
+    assert!(server
+        .requests()
+        .iter()
+        .all(|request| request.method == "GET"));
+    assert_eq!(
+        server.paths(),
+        [
+            "/v4/domains?page=1&page_size=500",
+            "/v4/domains/7/records?page=1&page_size=500",
+            "/v4/domains/7/records?page=1&page_size=500"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn configured_record_fields_are_sent() {
+    let server = zone_server("");
+    let mut config = server.config();
+    config.record = RecordConfig {
+        ttl_sec: 120,
+        priority: Some(10),
+    };
+    let client = LinodeDnsClient::new(&config).unwrap();
+
+    client
+        .add_txt_record("_acme-challenge.example.com", "abc")
+        .await
+        .unwrap();
+    assert_eq!(
+        server.requests()[2].body,
+        r#"{"type":"TXT","name":"_acme-challenge","target":"abc","ttl_sec":120,"priority":10}"#
+    );
+}
+
+#[tokio::test]
+async fn existing_identical_record_is_reused() {
+    let server = zone_server(
+        &[
+            r#"{"id":70,"type":"TXT","name":"_acme-challenge.www","target":"old"}"#,
+            r#"{"id":71,"type":"TXT","name":"_acme-challenge.www","target":"abc"}"#,
+        ]
+        .join(","),
+    );
+
+    let record = server
+        .client()
+        .add_txt_record("_acme-challenge.www.example.com", "abc")
+        .await
+        .unwrap();
+    assert_eq!(record.record_id, 71);
+    assert!(record.reused);
+    assert!(server
+        .requests()
+        .iter()
+        .all(|request| request.method == "GET"));
+}
+
+#[tokio::test]
+async fn clean_challenge_removes_every_duplicate() {
+    let server = zone_server_with(
+        &[
+            r#"{"id":71,"type":"TXT","name":"_acme-challenge.www","target":"abc"}"#,
+            r#"{"id":72,"type":"TXT","name":"_acme-challenge.www","target":"abc"}"#,
+            r#"{"id":73,"type":"TXT","name":"_acme-challenge.www","target":"abc"}"#,
+            r#"{"id":74,"type":"TXT","name":"_acme-challenge.www","target":"other"}"#,
+        ]
+        .join(","),
+        |request| match request.path.as_str() {
+            "/v4/domains/7/records/72" => (500, "{}".to_owned()),
+            _ => (200, "{}".to_owned()),
+        },
+    );
+    let challenges = [Challenge {
+        domain: "www.example.com".to_owned(),
+        token_filename: "file".to_owned(),
+        token_value: "abc".to_owned(),
+    }];
+
+    //the failed DELETE is reported after the others have still been tried
+    let result = server.client().clean_challenge(&challenges).await;
+    assert_eq!(result.unwrap_err().exit_code(), 8);
+
+    let mut deleted: Vec<String> = server
+        .requests()
+        .into_iter()
+        .filter(|request| request.method == "DELETE")
+        .map(|request| request.path)
+        .collect();
+    deleted.sort();
+    deleted.dedup();
+    assert_eq!(
+        deleted,
+        [
+            "/v4/domains/7/records/71",
+            "/v4/domains/7/records/72",
+            "/v4/domains/7/records/73"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn clean_challenge_lists_each_zone_once() {
+    let server = zone_server(
+        &(1..=5)
+            .map(|n| {
+                format!(r#"{{"id":7{n},"type":"TXT","name":"_acme-challenge.host{n}","target":"token{n}"}}"#)
+            })
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    let mut config = server.config();
+    config.clean_concurrency = 2;
+    let challenges: Vec<Challenge> = (1..=5)
+        .map(|n| Challenge {
+            domain: format!("host{n}.example.com"),
+            token_filename: format!("file{n}"),
+            token_value: format!("token{n}"),
+        })
+        .collect();
+
+    LinodeDnsClient::new(&config)
+        .unwrap()
+        .clean_challenge(&challenges)
+        .await
+        .unwrap();
+
+    let requests = server.requests();
+    let count = |method: &str| requests.iter().filter(|r| r.method == method).count();
+    assert_eq!(count("GET"), 2);
+    assert_eq!(count("DELETE"), 5);
+}
+
+#[tokio::test]
+async fn lookups_are_filtered_on_the_server() {
+    let server =
+        MockServer::start(
+            |request| match (request.method.as_str(), request.header("X-Filter")) {
+                ("GET", Some(filter)) if request.path.starts_with("/v4/domains?") => {
+                    assert!(filter.contains(r#"{"domain":"example.com"}"#));
+                    (200, page(r#"{"id":7,"domain":"example.com"}"#, 1, 1))
+                }
+                ("GET", Some(filter)) => {
+                    assert_eq!(filter, r#"{"name":"_acme-challenge.www","type":"TXT"}"#);
+                    (
+                        200,
+                        page(
+                            r#"{"id":71,"type":"TXT","name":"_acme-challenge.www","target":"abc"}"#,
+                            1,
+                            1,
+                        ),
+                    )
+                }
+                _ => (500, "{}".to_owned()),
+            },
+        );
+    let client = server.client();
+
+    let (subdomain, _zone, domain_id) = client.get_domain_info("www.example.com").await.unwrap();
+    let id = client
+        .get_record_id(domain_id, &subdomain, "abc")
+        .await
+        .unwrap();
+    assert_eq!(id, Some(71));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn rejected_filters_fall_back_to_full_listing() {
+    //unsupported filters are a 400, ones too large for the server e.g. a 431
+    for status in [400, 431] {
+        rejected_filter_falls_back(status).await;
+    }
+}
+
+async fn rejected_filter_falls_back(status: u16) {
+    let server = MockServer::start(move |request| {
+        match (request.method.as_str(), request.header("X-Filter")) {
+            (_, Some(_)) => (
+                status,
+                r#"{"errors":[{"reason":"Filtering is not supported"}]}"#.to_owned(),
+            ),
+            ("GET", None) if request.path.starts_with("/v4/domains?") => (
+                200,
+                page(
+                    r#"{"id":7,"domain":"example.com"},{"id":8,"domain":"www.example.com.au"}"#,
+                    1,
+                    1,
+                ),
+            ),
+            _ => (200, page("", 1, 1)),
+        }
+    });
+
+    let (subdomain, zone, domain_id) = server
+        .client()
+        .get_domain_info("www.example.com")
+        .await
+        .unwrap();
+    assert_eq!(
+        (subdomain.as_str(), zone.as_str(), domain_id),
+        ("www", "example.com", 7)
+    );
+    assert_eq!(server.requests().len(), 2);
+}
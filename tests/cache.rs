@@ -0,0 +1,115 @@
+mod common;
+
+use common::{zone_server, MockServer};
+use linode_dns::{Domain, LinodeDnsClient, ZoneCache};
+use std::{env, fs, process};
+
+fn domain_listings(server: &MockServer) -> usize {
+    server
+        .paths()
+        .iter()
+        .filter(|path| path.starts_with("/v4/domains?"))
+        .count()
+}
+
+#[tokio::test]
+async fn concurrent_lookups_share_one_listing() {
+    let server = zone_server("");
+    let client = server.client();
+
+    let mut tasks = Vec::new();
+    for _ in 0..4 {
+        let client = client.clone();
+        tasks.push(tokio::spawn(async move {
+            client
+                .get_domain_info("www.example.com")
+                .await
+                .map(|(_, _, id)| id)
+        }));
+    }
+    for task in tasks {
+        assert_eq!(task.await.unwrap().unwrap(), 7);
+    }
+    assert_eq!(domain_listings(&server), 1);
+}
+
+#[tokio::test]
+async fn challenge_names_use_the_prefetched_zones() {
+    let server = zone_server("");
+    let client = server.client();
+
+    client.prefetch_zones(&["www.example.com"]).await.unwrap();
+    let (subdomain, _, domain_id) = client
+        .get_domain_info("_acme-challenge.www.example.com")
+        .await
+        .unwrap();
+    assert_eq!((subdomain.as_str(), domain_id), ("_acme-challenge.www", 7));
+
+    let filters: Vec<String> = server
+        .requests()
+        .iter()
+        .filter_map(|request| request.header("X-Filter").map(str::to_owned))
+        .collect();
+    assert_eq!(filters.len(), 1);
+    assert!(!filters[0].contains("_acme-challenge"));
+}
+
+#[tokio::test]
+async fn zone_lookups_are_sent_in_batches() {
+    let server = zone_server("");
+    let hosts: Vec<String> = (1..=60).map(|n| format!("host{n}.example.com")).collect();
+    let names: Vec<&str> = hosts.iter().map(String::as_str).collect();
+
+    server.client().prefetch_zones(&names).await.unwrap();
+
+    //60 hosts plus example.com and com
+    let batches: Vec<usize> = server
+        .requests()
+        .iter()
+        .filter_map(|request| request.header("X-Filter"))
+        .map(|filter| filter.matches(r#""domain""#).count())
+        .collect();
+    assert_eq!(batches, [50, 12]);
+}
+
+#[tokio::test]
+async fn zone_cache_file_is_reused_until_it_expires() {
+    let server = zone_server("");
+    let path = env::temp_dir().join(format!("linode-dns-zones-{}.json", process::id()));
+    let mut config = server.config();
+    config.zone_cache_file = Some(path.to_owned());
+
+    //written by the first client, read back by the next one
+    LinodeDnsClient::new(&config)
+        .unwrap()
+        .get_domain_info("www.example.com")
+        .await
+        .unwrap();
+    let (_, zone, _) = LinodeDnsClient::new(&config)
+        .unwrap()
+        .get_domain_info("www.example.com")
+        .await
+        .unwrap();
+    assert_eq!(zone, "example.com");
+    assert_eq!(domain_listings(&server), 1);
+
+    //an expired cache is fetched again
+    ZoneCache {
+        fetched_at: 0,
+        domains: vec![Domain {
+            id: 8,
+            domain: "example.org".to_owned(),
+        }],
+    }
+    .save(&path)
+    .unwrap();
+    let (_, zone, _) = LinodeDnsClient::new(&config)
+        .unwrap()
+        .get_domain_info("www.example.com")
+        .await
+        .unwrap();
+    assert_eq!(zone, "example.com");
+    assert_eq!(domain_listings(&server), 2);
+
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,160 @@
+//Shared by several test crates, not every one uses every helper
+#![allow(dead_code)]
+
+use linode_dns::{Config, LinodeDnsClient, RetryConfig};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+#[derive(Clone)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl MockRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+//Minimal stand-in for the Linode API, answers every request with the status and JSON body the handler returns
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockServer {
+    pub fn start(handler: impl Fn(&MockRequest) -> (u16, String) + Send + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+
+                let mut headers = Vec::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                        headers.push((name.to_owned(), value.trim().to_owned()));
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let mut parts = request_line.split_whitespace();
+                let request = MockRequest {
+                    method: parts.next().unwrap().to_owned(),
+                    path: parts.next().unwrap().to_owned(),
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                };
+                let (status, body) = handler(&request);
+                seen.lock().unwrap().push(request);
+
+                //rate limited responses ask for a long wait, which max_delay_ms cuts short
+                let retry_after = match status {
+                    429 => "Retry-After: 3600\r\n",
+                    _ => "",
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} Mock\r\n{retry_after}Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+
+        MockServer {
+            url: format!("http://{address}"),
+            requests,
+        }
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn paths(&self) -> Vec<String> {
+        self.requests()
+            .into_iter()
+            .map(|request| request.path)
+            .collect()
+    }
+
+    //settings pointing at this server, with no waiting between retries so failure tests stay quick
+    pub fn config(&self) -> Config {
+        Config {
+            token: Some("test-token".to_owned()),
+            api_url: format!("{}/v4", self.url),
+            retry: RetryConfig {
+                max_attempts: 3,
+                base_delay_ms: 0,
+                max_delay_ms: 0,
+            },
+            ..Config::default()
+        }
+    }
+
+    pub fn client(&self) -> LinodeDnsClient {
+        LinodeDnsClient::new(&self.config()).unwrap()
+    }
+}
+
+//page of a listing endpoint as Linode returns it
+pub fn page(data: &str, page: u32, pages: u32) -> String {
+    format!(r#"{{"data":[{data}],"page":{page},"pages":{pages},"results":0}}"#)
+}
+
+pub fn query_page(path: &str) -> u32 {
+    path.split(['?', '&'])
+        .find_map(|pair| pair.strip_prefix("page="))
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+//example.com as domain 7 holding the given records, with every write answered by the writes handler
+pub fn zone_server_with(
+    records: &str,
+    writes: impl Fn(&MockRequest) -> (u16, String) + Send + 'static,
+) -> MockServer {
+    let records = page(records, 1, 1);
+    MockServer::start(move |request| match request.method.as_str() {
+        "GET" if request.path.starts_with("/v4/domains?") => {
+            (200, page(r#"{"id":7,"domain":"example.com"}"#, 1, 1))
+        }
+        "GET" => (200, records.to_owned()),
+        _ => writes(request),
+    })
+}
+
+//new records are created as id 71 and every other write succeeds
+pub fn zone_server(records: &str) -> MockServer {
+    zone_server_with(records, |request| match request.method.as_str() {
+        "POST" => (200, r#"{"id":71}"#.to_owned()),
+        _ => (200, "{}".to_owned()),
+    })
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::{zone_server, zone_server_with};
+use linode_dns::{Challenge, LinodeDnsClient, RecordJournal};
+use std::{env, net::SocketAddr, process};
+
+#[tokio::test]
+async fn created_records_are_rolled_back_when_one_fails() {
+    let server = zone_server_with("", |request| match request.method.as_str() {
+        "POST" if request.body.contains("_acme-challenge.bad") => (
+            400,
+            r#"{"errors":[{"field":"name","reason":"Invalid name"}]}"#.to_owned(),
+        ),
+        "POST" if request.body.contains("_acme-challenge.one") => (200, r#"{"id":71}"#.to_owned()),
+        "POST" => (200, r#"{"id":72}"#.to_owned()),
+        _ => (200, "{}".to_owned()),
+    });
+    let path = env::temp_dir().join(format!("linode-dns-rollback-{}.json", process::id()));
+    let mut config = server.config();
+    config.journal_file = Some(path.to_owned());
+    let client = LinodeDnsClient::new(&config).unwrap();
+
+    let challenges: Vec<Challenge> = ["one", "bad", "two"]
+        .iter()
+        .map(|host| Challenge {
+            domain: format!("{host}.example.com"),
+            token_filename: format!("file-{host}"),
+            token_value: format!("token-{host}"),
+        })
+        .collect();
+
+    let error = client.deploy_challenge(&challenges).await.unwrap_err();
+    assert_eq!(error.exit_code(), 7);
+
+    let mut deleted: Vec<String> = server
+        .requests()
+        .into_iter()
+        .filter(|request| request.method == "DELETE")
+        .map(|request| request.path)
+        .collect();
+    deleted.sort();
+    assert_eq!(
+        deleted,
+        ["/v4/domains/7/records/71", "/v4/domains/7/records/72"]
+    );
+    assert!(RecordJournal::load(&path).unwrap().records.is_empty());
+}
+
+//paused time skips the resolver's own timeouts against the unreachable nameserver
+#[tokio::test(start_paused = true)]
+async fn unpropagated_records_are_rolled_back() {
+    let server = zone_server("");
+    let mut config = server.config();
+    config.propagation.timeout_secs = 0;
+    //TEST-NET-1, nothing answers there
+    config.propagation.nameservers = vec![SocketAddr::from(([192, 0, 2, 1], 53))];
+    let client = LinodeDnsClient::new(&config).unwrap();
+
+    let challenges = [Challenge {
+        domain: "www.example.com".to_owned(),
+        token_filename: "file".to_owned(),
+        token_value: "abc".to_owned(),
+    }];
+    let error = client.deploy_challenge(&challenges).await.unwrap_err();
+    assert_eq!(error.exit_code(), 9);
+
+    let deleted: Vec<String> = server
+        .requests()
+        .into_iter()
+        .filter(|request| request.method == "DELETE")
+        .map(|request| request.path)
+        .collect();
+    assert_eq!(deleted, ["/v4/domains/7/records/71"]);
+}
@@ -0,0 +1,138 @@
+//copying with permissions and running commands through sh are unix only
+#![cfg(unix)]
+
+use linode_dns::{run_deploy_actions, CertFile, Config, DeployAction, DeployCert};
+use std::{
+    env, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process,
+};
+
+//a fresh directory holding dehydrated's four files
+fn certificate(name: &str) -> (PathBuf, DeployCert) {
+    let dir = env::temp_dir().join(format!("linode-dns-{name}-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for file in ["privkey", "cert", "fullchain", "chain"] {
+        fs::write(dir.join(format!("{file}.pem")), format!("{file}\n")).unwrap();
+    }
+    let cert = DeployCert {
+        domain: "example.com".to_owned(),
+        keyfile: dir.join("privkey.pem"),
+        certfile: dir.join("cert.pem"),
+        fullchainfile: dir.join("fullchain.pem"),
+        chainfile: dir.join("chain.pem"),
+        timestamp: "1700000000".to_owned(),
+    };
+    (dir, cert)
+}
+
+fn mode(path: &Path) -> u32 {
+    fs::metadata(path).unwrap().permissions().mode() & 0o777
+}
+
+#[tokio::test]
+async fn files_are_copied_and_combined_then_the_command_runs() {
+    let (dir, cert) = certificate("deploy");
+    let actions = vec![
+        DeployAction::Copy {
+            file: CertFile::Fullchain,
+            to: dir.join("out-fullchain.pem"),
+            owner: None,
+            group: None,
+            mode: Some("0640".to_owned()),
+        },
+        DeployAction::Copy {
+            file: CertFile::Key,
+            to: dir.join("out-key.pem"),
+            owner: None,
+            group: None,
+            mode: None,
+        },
+        DeployAction::Combine {
+            to: dir.join("haproxy.pem"),
+            owner: None,
+            group: None,
+            mode: None,
+        },
+        DeployAction::Command {
+            command: format!("echo \"$DOMAIN\" > {}", dir.join("reloaded").display()),
+            timeout_secs: 10,
+        },
+    ];
+
+    let config = Config {
+        deploy: actions,
+        ..Config::default()
+    };
+    run_deploy_actions(&config, &cert).await.unwrap();
+
+    assert_eq!(
+        fs::read_to_string(dir.join("out-fullchain.pem")).unwrap(),
+        "fullchain\n"
+    );
+    assert_eq!(mode(&dir.join("out-fullchain.pem")), 0o640);
+    assert_eq!(mode(&dir.join("out-key.pem")), 0o600);
+    assert_eq!(
+        fs::read_to_string(dir.join("haproxy.pem")).unwrap(),
+        "privkey\nfullchain\n"
+    );
+    assert_eq!(mode(&dir.join("haproxy.pem")), 0o600);
+    assert_eq!(
+        fs::read_to_string(dir.join("reloaded")).unwrap(),
+        "example.com\n"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn failing_or_hanging_commands_stop_the_pipeline() {
+    let (dir, cert) = certificate("deploy-fail");
+
+    for command in ["exit 3", "sleep 30"] {
+        let actions = vec![
+            DeployAction::Command {
+                command: command.to_owned(),
+                timeout_secs: 1,
+            },
+            DeployAction::Copy {
+                file: CertFile::Cert,
+                to: dir.join("out-cert.pem"),
+                owner: None,
+                group: None,
+                mode: None,
+            },
+        ];
+        let config = Config {
+            deploy: actions,
+            ..Config::default()
+        };
+        let error = run_deploy_actions(&config, &cert).await.unwrap_err();
+        assert_eq!(error.exit_code(), 12);
+        assert!(!dir.join("out-cert.pem").exists());
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn invalid_modes_are_rejected() {
+    let (dir, cert) = certificate("deploy-mode");
+
+    for mode in ["0999", "10644", "rw-r-----"] {
+        let config = Config {
+            deploy: vec![DeployAction::Copy {
+                file: CertFile::Cert,
+                to: dir.join("out-cert.pem"),
+                owner: None,
+                group: None,
+                mode: Some(mode.to_owned()),
+            }],
+            ..Config::default()
+        };
+        let error = run_deploy_actions(&config, &cert).await.unwrap_err();
+        assert!(error.to_string().contains("is not an octal permission"));
+        assert!(!dir.join("out-cert.pem").exists());
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::{zone_server, MockServer};
+use linode_dns::LinodeDnsClient;
+
+fn server() -> MockServer {
+    zone_server(
+        &[
+            //stale challenge, and one whose update keeps it fresh
+            r#"{"id":71,"type":"TXT","name":"_acme-challenge","target":"a","created":"2018-01-01T00:01:01","updated":"2018-01-01T00:01:01"}"#,
+            r#"{"id":72,"type":"TXT","name":"_acme-challenge.www","target":"b","created":"2018-01-01T00:01:01","updated":"2999-01-01T00:00:00"}"#,
+            //old but not challenge records
+            r#"{"id":73,"type":"TXT","name":"www","target":"c","created":"2018-01-01T00:01:01","updated":"2018-01-01T00:01:01"}"#,
+            r#"{"id":74,"type":"A","name":"_acme-challenge","target":"192.0.2.1","created":"2018-01-01T00:01:01","updated":"2018-01-01T00:01:01"}"#,
+        ]
+        .join(","),
+    )
+}
+
+#[tokio::test]
+async fn only_stale_challenge_records_are_removed() {
+    let server = server();
+    server.client().collect_garbage(24).await.unwrap();
+
+    let deletes: Vec<String> = server
+        .requests()
+        .into_iter()
+        .filter(|request| request.method == "DELETE")
+        .map(|request| request.path)
+        .collect();
+    assert_eq!(deletes, ["/v4/domains/7/records/71"]);
+}
+
+#[tokio::test]
+async fn dry_run_removes_nothing() {
+    let server = server();
+    let mut config = server.config();
+    config.dry_run = true;
+    LinodeDnsClient::new(&config)
+        .unwrap()
+        .collect_garbage(24)
+        .await
+        .unwrap();
+
+    assert!(server
+        .requests()
+        .iter()
+        .all(|request| request.method == "GET"));
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use common::{zone_server, MockServer};
+use linode_dns::{AddedRecord, Challenge, LinodeDnsClient, RecordJournal};
+use std::{env, fs, process};
+
+fn record(domain_id: i32, record_id: i32) -> AddedRecord {
+    AddedRecord {
+        name: "_acme-challenge.www.example.com".to_owned(),
+        zone: "example.com".to_owned(),
+        domain_id,
+        record_id,
+        token: format!("token{record_id}"),
+        reused: false,
+    }
+}
+
+#[tokio::test]
+async fn journaled_records_are_removed_and_failures_kept() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/v4/domains/7/records/72" => (404, r#"{"errors":[{"reason":"Not found"}]}"#.to_owned()),
+        "/v4/domains/8/records/82" => (400, r#"{"errors":[{"reason":"Invalid"}]}"#.to_owned()),
+        _ => (200, "{}".to_owned()),
+    });
+    let path = env::temp_dir().join(format!("linode-dns-journal-{}.json", process::id()));
+    RecordJournal {
+        records: vec![record(7, 71), record(7, 72), record(8, 81), record(8, 82)],
+    }
+    .save(&path)
+    .unwrap();
+
+    let mut config = server.config();
+    config.journal_file = Some(path.to_owned());
+    let client = LinodeDnsClient::new(&config).unwrap();
+
+    assert!(client.remove_journaled_records().await.is_err());
+    let remaining = RecordJournal::load(&path).unwrap();
+    let ids: Vec<i32> = remaining.records.iter().map(|r| r.record_id).collect();
+    //72 was already gone, only the record that really failed is kept
+    assert_eq!(ids, [82]);
+    assert_eq!(server.requests().len(), 4);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn clean_challenge_uses_journal_before_scanning() {
+    let server =
+        zone_server(r#"{"id":81,"type":"TXT","name":"_acme-challenge","target":"token81"}"#);
+    let path = env::temp_dir().join(format!("linode-dns-clean-{}.json", process::id()));
+    RecordJournal {
+        records: vec![record(7, 71)],
+    }
+    .save(&path)
+    .unwrap();
+
+    let mut config = server.config();
+    config.journal_file = Some(path.to_owned());
+    let client = LinodeDnsClient::new(&config).unwrap();
+
+    let challenges = [
+        Challenge {
+            domain: "www.example.com".to_owned(),
+            token_filename: "file71".to_owned(),
+            token_value: "token71".to_owned(),
+        },
+        Challenge {
+            domain: "example.com".to_owned(),
+            token_filename: "file81".to_owned(),
+            token_value: "token81".to_owned(),
+        },
+    ];
+    client.clean_challenge(&challenges).await.unwrap();
+
+    //journaled record deleted directly, the other found by listing the zone (deletes run concurrently)
+    let mut requests: Vec<String> = server
+        .requests()
+        .iter()
+        .map(|request| {
+            format!(
+                "{} {}",
+                request.method,
+                request.path.split('?').next().unwrap()
+            )
+        })
+        .collect();
+    requests.sort();
+    assert_eq!(
+        requests,
+        [
+            "DELETE /v4/domains/7/records/71",
+            "DELETE /v4/domains/7/records/81",
+            "GET /v4/domains",
+            "GET /v4/domains/7/records",
+        ]
+    );
+    assert!(!path.exists());
+}